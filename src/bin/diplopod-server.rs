@@ -0,0 +1,635 @@
+//! Runs games of diplopod for players on different machines.
+//!
+//! The server waits until enough players have joined, runs the simulation of the game and sends
+//! the input of every tick to all players (see [`diplopod::net`]). Once every player has received
+//! the end of the game, the same players are seated for the next one, which starts as soon as all
+//! of them have joined again.
+
+use anyhow::{Context, Result, anyhow};
+use diplopod::net::{
+    ClientMessage, DEFAULT_PORT, MAX_MESSAGE_SIZE, MAX_TICKS, ServerMessage, TIMEOUT,
+};
+use diplopod::simulation::{Direction, Event, Input, Mode, Rules, Simulation};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Time between the start of a game and its first tick, which gives every player the chance to
+/// set up the game.
+const START_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest time to wait for a message while no game is running.
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// A player of the next or the running game.
+struct Client {
+    address: SocketAddr,
+    direction: Direction,
+    /// Number of ticks the player has received.
+    received: u64,
+    /// Whether the player has joined the next game.
+    ready: bool,
+    last_seen: Instant,
+}
+
+struct Game {
+    simulation: Simulation,
+    /// The input of every tick so far.
+    inputs: Vec<Input>,
+    next_tick: Instant,
+    over: bool,
+}
+
+struct Server {
+    socket: UdpSocket,
+    rules: Rules,
+    seed: Option<u64>,
+    clients: Vec<Client>,
+    game: Option<Game>,
+}
+
+fn main() -> Result<()> {
+    let address = arg_value("--address").unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PORT));
+
+    let mode = match arg_value("--mode").as_deref() {
+        None | Some("versus") => Mode::Versus,
+        Some("coop") => Mode::Coop,
+        Some(mode) => return Err(anyhow!("Unknown mode '{}', use versus or coop", mode)),
+    };
+
+    let seed = arg_value("--seed")
+        .map(|seed| seed.parse())
+        .transpose()
+        .context("Invalid seed")?;
+
+    let socket =
+        UdpSocket::bind(&address).with_context(|| format!("Unable to listen on {}", address))?;
+
+    println!(
+        "Waiting for {} players on {}",
+        mode.players(),
+        socket.local_addr()?
+    );
+
+    Server {
+        socket,
+        rules: Rules {
+            mode,
+            ..Rules::default()
+        },
+        seed,
+        clients: Vec::new(),
+        game: None,
+    }
+    .run()
+}
+
+/// Returns the value following the given option on the command line.
+fn arg_value(option: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != option).nth(1)
+}
+
+impl Server {
+    fn run(&mut self) -> Result<()> {
+        loop {
+            self.step()?;
+        }
+    }
+
+    /// Waits for the next message or tick and handles it.
+    fn step(&mut self) -> Result<()> {
+        let mut buffer = [0; MAX_MESSAGE_SIZE];
+
+        let wait = match &self.game {
+            Some(game) if !game.over => game.next_tick.saturating_duration_since(Instant::now()),
+            _ => IDLE_WAIT,
+        };
+
+        // a timeout of zero would block forever
+        self.socket
+            .set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+
+        match self.socket.recv_from(&mut buffer) {
+            Ok((length, address)) => match std::str::from_utf8(&buffer[..length])
+                .map_err(anyhow::Error::from)
+                .and_then(str::parse)
+            {
+                Ok(message) => self.receive(message, address),
+                Err(e) => eprintln!("Ignoring invalid message from {}: {:#}", address, e),
+            },
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionReset
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        self.update();
+
+        Ok(())
+    }
+
+    fn receive(&mut self, message: ClientMessage, address: SocketAddr) {
+        let player = self
+            .clients
+            .iter()
+            .position(|client| client.address == address);
+
+        match (message, player) {
+            (ClientMessage::Join, Some(player)) => {
+                let client = &mut self.clients[player];
+                client.last_seen = Instant::now();
+
+                match &self.game {
+                    // a player who joins again after the game is over wants a rematch, so the
+                    // end of the game must have arrived
+                    Some(game) if game.over => {
+                        client.received = game.inputs.len() as u64;
+                        client.ready = true;
+                    }
+                    Some(_) => {}
+                    None => client.ready = true,
+                }
+
+                if !self.start_when_ready() {
+                    self.greet(player);
+                }
+            }
+
+            (ClientMessage::Join, None) => {
+                if self.game.is_some() || self.clients.len() >= self.rules.mode.players() {
+                    return;
+                }
+
+                println!("Player {} joined from {}", self.clients.len() + 1, address);
+
+                self.clients.push(Client {
+                    address,
+                    direction: Direction::NONE,
+                    received: 0,
+                    ready: true,
+                    last_seen: Instant::now(),
+                });
+
+                if !self.start_when_ready() {
+                    self.greet(self.clients.len() - 1);
+                }
+            }
+
+            (
+                ClientMessage::Input {
+                    received,
+                    direction,
+                },
+                Some(player),
+            ) => {
+                let client = &mut self.clients[player];
+                client.direction = direction;
+                client.received = client.received.max(received);
+                client.last_seen = Instant::now();
+
+                self.send_ticks(player);
+            }
+
+            (ClientMessage::Leave, Some(player)) => {
+                println!("Player {} left", player + 1);
+                self.clients.remove(player);
+
+                if self.game.is_some() {
+                    self.abort();
+                }
+            }
+
+            // messages of players who are not part of the game (anymore) are ignored
+            (_, None) => {}
+        }
+    }
+
+    /// Drops players who stopped sending, advances the game once per tick and ends it once every
+    /// player has received all of its ticks.
+    fn update(&mut self) {
+        let now = Instant::now();
+
+        if let Some(player) = self
+            .clients
+            .iter()
+            .position(|client| now.duration_since(client.last_seen) > TIMEOUT)
+        {
+            println!("Player {} timed out", player + 1);
+            self.clients.remove(player);
+
+            if self.game.is_some() {
+                self.abort();
+            }
+        }
+
+        let Some(game) = &mut self.game else {
+            return;
+        };
+
+        if game.over {
+            let ticks = game.inputs.len() as u64;
+
+            if self.clients.iter().all(|client| client.received >= ticks) {
+                self.reset();
+                self.start_when_ready();
+            }
+
+            return;
+        }
+
+        if now < game.next_tick {
+            return;
+        }
+
+        let mut input = Input::default();
+
+        for (player, client) in self.clients.iter().enumerate() {
+            input.directions[player] = client.direction;
+        }
+
+        let events = game.simulation.tick(input);
+        game.inputs.push(input);
+        game.next_tick += game.simulation.tick_duration();
+
+        if events
+            .iter()
+            .any(|event| matches!(event, Event::GameOver | Event::GoalReached))
+        {
+            game.over = true;
+
+            match game.simulation.winner() {
+                Some(winner) => println!("Player {} won", winner + 1),
+                None => println!(
+                    "Game over after {} ticks with a length of {}",
+                    game.simulation.ticks(),
+                    game.simulation.length()
+                ),
+            }
+        }
+
+        for player in 0..self.clients.len() {
+            self.send_ticks(player);
+        }
+    }
+
+    /// Starts the next game once all of its players have joined. Returns `true` if it was
+    /// started.
+    fn start_when_ready(&mut self) -> bool {
+        let ready = self.game.is_none()
+            && self.clients.len() == self.rules.mode.players()
+            && self.clients.iter().all(|client| client.ready);
+
+        if ready {
+            self.start();
+        }
+
+        ready
+    }
+
+    fn start(&mut self) {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        println!("Starting game with seed {}", seed);
+
+        self.game = Some(Game {
+            simulation: Simulation::new(seed, self.rules),
+            inputs: Vec::new(),
+            next_tick: Instant::now() + START_DELAY,
+            over: false,
+        });
+
+        // players who waited for the rematch confirmed the ticks of the game before
+        for player in 0..self.clients.len() {
+            let client = &mut self.clients[player];
+            client.direction = Direction::NONE;
+            client.received = 0;
+            client.ready = false;
+
+            self.greet(player);
+        }
+    }
+
+    /// Tells a player whether the game has started. A player who waits for a rematch isn't told
+    /// about the game that is over.
+    fn greet(&self, player: usize) {
+        let message = match &self.game {
+            Some(game) if !game.over => ServerMessage::Start {
+                player,
+                seed: game.simulation.seed(),
+                rules: game.simulation.rules(),
+            },
+            _ => ServerMessage::Waiting { player },
+        };
+
+        self.send(player, &message);
+    }
+
+    /// Sends the ticks a player has not received yet.
+    fn send_ticks(&self, player: usize) {
+        let Some(game) = &self.game else {
+            return;
+        };
+
+        let received = self.clients[player].received;
+        let inputs: Vec<Input> = game
+            .inputs
+            .iter()
+            .skip(received as usize)
+            .take(MAX_TICKS)
+            .copied()
+            .collect();
+
+        if !inputs.is_empty() {
+            self.send(
+                player,
+                &ServerMessage::Ticks {
+                    first: received + 1,
+                    inputs,
+                },
+            );
+        }
+    }
+
+    /// Ends the game for the remaining players, because one of them is gone.
+    fn abort(&mut self) {
+        if self.game.as_ref().is_some_and(|game| !game.over) {
+            for player in 0..self.clients.len() {
+                self.send(player, &ServerMessage::Abort);
+            }
+        }
+
+        self.reset();
+    }
+
+    /// Waits for the players of the next game. The remaining players keep their seats.
+    fn reset(&mut self) {
+        self.game = None;
+
+        println!("Waiting for {} players", self.rules.mode.players());
+    }
+
+    fn send(&self, player: usize, message: &ServerMessage) {
+        let address = self.clients[player].address;
+
+        if let Err(e) = self.socket.send_to(message.to_string().as_bytes(), address) {
+            eprintln!("Unable to send to {}: {}", address, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A player on the same machine as the server, who simulates the game like the game does.
+    struct Player {
+        socket: UdpSocket,
+        player: Option<usize>,
+        simulation: Option<Simulation>,
+    }
+
+    impl Player {
+        fn new(server: &Server) -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.connect(server.socket.local_addr().unwrap()).unwrap();
+            socket.set_nonblocking(true).unwrap();
+
+            Self {
+                socket,
+                player: None,
+                simulation: None,
+            }
+        }
+
+        fn send(&self, message: ClientMessage) {
+            self.socket.send(message.to_string().as_bytes()).unwrap();
+        }
+
+        fn ticks(&self) -> u64 {
+            self.simulation.as_ref().map_or(0, Simulation::ticks)
+        }
+
+        /// Handles all messages that arrived and returns them.
+        fn receive(&mut self) -> Vec<ServerMessage> {
+            let mut buffer = [0; MAX_MESSAGE_SIZE];
+            let mut messages = Vec::new();
+
+            while let Ok(length) = self.socket.recv(&mut buffer) {
+                let message: ServerMessage = std::str::from_utf8(&buffer[..length])
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+
+                match &message {
+                    ServerMessage::Waiting { player } => self.player = Some(*player),
+                    ServerMessage::Start {
+                        player,
+                        seed,
+                        rules,
+                    } => {
+                        self.player = Some(*player);
+                        self.simulation = Some(Simulation::new(*seed, *rules));
+                    }
+                    ServerMessage::Ticks { first, inputs } => {
+                        if let Some(simulation) = &mut self.simulation {
+                            for (tick, input) in (*first..).zip(inputs) {
+                                if tick == simulation.ticks() + 1 {
+                                    simulation.tick(*input);
+                                }
+                            }
+                        }
+                    }
+                    ServerMessage::Abort => {}
+                }
+
+                messages.push(message);
+            }
+
+            messages
+        }
+    }
+
+    fn server() -> Server {
+        Server {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            rules: Rules {
+                mode: Mode::Versus,
+                ..Rules::default()
+            },
+            seed: Some(7),
+            clients: Vec::new(),
+            game: None,
+        }
+    }
+
+    /// Lets the server handle one message for every player.
+    fn step(server: &mut Server, players: &[Player]) {
+        for _ in players {
+            server.step().unwrap();
+        }
+    }
+
+    /// Starts a game of two players and plays the given number of ticks, unless it is over
+    /// before. Returns the simulation of the server after the last tick.
+    fn play(server: &mut Server, players: &mut [Player], ticks: u64) -> Option<Simulation> {
+        for player in players.iter() {
+            player.send(ClientMessage::Join);
+        }
+
+        step(server, players);
+
+        for player in players.iter_mut() {
+            player.receive();
+        }
+
+        let mut simulation = None;
+
+        for tick in 0..ticks {
+            // the next tick is due right away
+            if let Some(game) = &mut server.game {
+                game.next_tick = Instant::now();
+            }
+
+            for (index, player) in players.iter().enumerate() {
+                // the diplopods start on opposite sides and zigzag away from each other
+                let sign = if index == 0 { 1 } else { -1 };
+                let direction = if (tick / 4).is_multiple_of(2) {
+                    Direction::new(0, sign)
+                } else {
+                    Direction::new(sign, 0)
+                };
+
+                player.send(ClientMessage::Input {
+                    received: player.ticks(),
+                    direction,
+                });
+            }
+
+            step(server, players);
+
+            for player in players.iter_mut() {
+                player.receive();
+            }
+
+            // the server forgets the game once every player has received its end
+            if let Some(game) = &server.game {
+                simulation = Some(game.simulation.clone());
+            }
+        }
+
+        simulation
+    }
+
+    #[test]
+    fn players_simulate_the_same_game_as_the_server() {
+        let mut server = server();
+        let mut players = [Player::new(&server), Player::new(&server)];
+
+        let simulation = play(&mut server, &mut players, 30).unwrap();
+
+        // the players catch up on the ticks they are missing
+
+        for _ in 0..10 {
+            for player in players.iter() {
+                player.send(ClientMessage::Input {
+                    received: player.ticks(),
+                    direction: Direction::NONE,
+                });
+            }
+
+            step(&mut server, &players);
+
+            for player in players.iter_mut() {
+                player.receive();
+            }
+        }
+
+        assert!(simulation.ticks() > 20);
+
+        for (index, player) in players.iter().enumerate() {
+            let played = player.simulation.as_ref().unwrap();
+
+            assert_eq!(player.player, Some(index));
+            assert_eq!(played.ticks(), simulation.ticks());
+            assert_eq!(played.replay(), simulation.replay());
+            assert_eq!(played.consumables(), simulation.consumables());
+
+            for (played, expected) in played.diplopods().iter().zip(simulation.diplopods()) {
+                assert_eq!(played.segments(), expected.segments());
+            }
+        }
+    }
+
+    #[test]
+    fn rematch_waits_until_every_player_has_seen_the_end() {
+        let mut server = server();
+        let mut players = [Player::new(&server), Player::new(&server)];
+
+        play(&mut server, &mut players, 3);
+        server.game.as_mut().unwrap().over = true;
+
+        // the first player wants a rematch before the second one has received the last tick
+        players[0].send(ClientMessage::Join);
+        server.step().unwrap();
+
+        assert!(server.game.is_some());
+        assert_eq!(server.clients.len(), 2);
+        assert!(
+            players[0]
+                .receive()
+                .contains(&ServerMessage::Waiting { player: 0 })
+        );
+
+        // nobody else can join the game that is over
+        let stranger = Player::new(&server);
+        stranger.send(ClientMessage::Join);
+        server.step().unwrap();
+
+        assert_eq!(server.clients.len(), 2);
+        assert!(server.game.is_some());
+
+        // the game is reset once the second player has received every tick
+        let ticks = server.game.as_ref().unwrap().inputs.len() as u64;
+        players[1].send(ClientMessage::Input {
+            received: ticks,
+            direction: Direction::NONE,
+        });
+        server.step().unwrap();
+
+        assert!(server.game.is_none());
+        assert_eq!(server.clients.len(), 2);
+
+        // the players read the results for longer than the timeout, but keep their seats by
+        // confirming the end of the game again and again
+        for _ in 0..3 {
+            for client in &mut server.clients {
+                client.last_seen -= TIMEOUT / 2;
+            }
+
+            for player in &players {
+                player.send(ClientMessage::Input {
+                    received: ticks,
+                    direction: Direction::NONE,
+                });
+            }
+
+            step(&mut server, &players);
+        }
+
+        assert_eq!(server.clients.len(), 2);
+
+        // the rematch starts once the second player has joined, too
+        players[1].send(ClientMessage::Join);
+        server.step().unwrap();
+
+        assert!(server.game.as_ref().is_some_and(|game| !game.over));
+
+        for (index, player) in players.iter_mut().enumerate() {
+            player.receive();
+            assert_eq!(player.player, Some(index));
+            assert_eq!(player.ticks(), 0);
+            assert_eq!(server.clients[index].received, 0);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use bevy::color::palettes::css::ANTIQUE_WHITE;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::Action;
+use crate::game::{CurrentGame, GoalReached, record_game};
+use crate::highscore::{InitialDelay, Lastscore, format_duration};
+use crate::level::LevelAsset;
+use crate::replay::Playback;
+use crate::save::Statistics;
+use crate::settings::Settings;
+use crate::simulation::level::Goal;
+
+use super::{GameState, despawn_screen};
+
+/// Adds a campaign of levels that have to be completed one after another by reaching their
+/// goals, together with a screen to select one of the levels that were unlocked so far.
+pub struct CampaignPlugin;
+
+/// Paths of the levels of the campaign within the assets, in the order they are played.
+pub const CAMPAIGN: [&str; 5] = [
+    "levels/campaign/01-first-steps.level",
+    "levels/campaign/02-pillars.level",
+    "levels/campaign/03-antidote.level",
+    "levels/campaign/04-zigzag.level",
+    "levels/campaign/05-labyrinth.level",
+];
+
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const NAME_COLOR: Color = Color::WHITE;
+const LOCKED_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+const ROW_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const ROW_SELECTED_BACKGROUND_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+
+/// Number of levels of the campaign that were completed. The level after them is the last one
+/// that is unlocked.
+#[derive(Clone, Default, Resource, Serialize, Deserialize)]
+pub struct CampaignProgress {
+    pub completed: usize,
+}
+
+impl CampaignProgress {
+    fn is_unlocked(&self, index: usize) -> bool {
+        index <= self.completed
+    }
+}
+
+/// Index of the campaign level that is played. It only exists while the campaign is played.
+#[derive(Resource)]
+pub struct CampaignLevel(pub usize);
+
+/// The levels of the campaign, loaded to show their names and goals on the level select screen.
+#[derive(Resource)]
+struct CampaignLevels(Vec<Handle<LevelAsset>>);
+
+/// Index of the selected level on the level select screen.
+#[derive(Default, Resource)]
+struct SelectedCampaignLevel(usize);
+
+#[derive(Component)]
+struct OnLevelSelectScreen;
+
+/// Shows the name and goal of a level of the campaign.
+#[derive(Component)]
+struct LevelRow(usize);
+
+#[derive(Component)]
+enum LevelText {
+    Name,
+    Goal,
+}
+
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::LevelSelect), setup_level_select)
+            .add_systems(
+                Update,
+                (
+                    (
+                        navigate.run_if(not(resource_exists::<InitialDelay>)),
+                        update_rows,
+                    )
+                        .chain()
+                        .run_if(in_state(GameState::LevelSelect)),
+                    finish_level
+                        .run_if(on_message::<GoalReached>)
+                        .run_if(resource_exists::<CampaignLevel>),
+                ),
+            )
+            .add_systems(
+                OnExit(GameState::LevelSelect),
+                despawn_screen::<OnLevelSelectScreen>,
+            )
+            .add_systems(OnEnter(GameState::Menu), leave_campaign)
+            .init_resource::<SelectedCampaignLevel>();
+    }
+}
+
+/// Describes the goal of a level for the player.
+pub fn describe_goal(goal: Option<Goal>) -> String {
+    match goal {
+        None => "Survive as long as possible".to_string(),
+        Some(Goal::Length(length)) => format!("Reach length {}", length),
+        Some(Goal::ClearPoison) => "Eat all poison".to_string(),
+        Some(Goal::Survive(duration)) => format!("Survive {}", format_duration(duration)),
+    }
+}
+
+/// Continues with the next level once the goal is reached. After the last level the player
+/// returns to the level select screen.
+#[allow(clippy::too_many_arguments)]
+fn finish_level(
+    mut commands: Commands,
+    campaign_level: Res<CampaignLevel>,
+    game: Res<CurrentGame>,
+    mut progress: ResMut<CampaignProgress>,
+    mut lastscore: ResMut<Lastscore>,
+    mut statistics: ResMut<Statistics>,
+    playback: Option<Res<Playback>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let index = campaign_level.0;
+
+    record_game(&game, playback.is_some(), &mut lastscore, &mut statistics);
+
+    progress.completed = progress.completed.max(index + 1);
+
+    if index + 1 < CAMPAIGN.len() {
+        commands.insert_resource(CampaignLevel(index + 1));
+        game_state.set(GameState::Loading);
+    } else {
+        commands.remove_resource::<CampaignLevel>();
+        game_state.set(GameState::LevelSelect);
+    }
+}
+
+fn leave_campaign(mut commands: Commands) {
+    commands.remove_resource::<CampaignLevel>();
+}
+
+fn navigate(
+    mut commands: Commands,
+    actions: Res<ButtonInput<Action>>,
+    progress: Res<CampaignProgress>,
+    mut selected: ResMut<SelectedCampaignLevel>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let count = CAMPAIGN.len();
+
+    if actions.just_pressed(Action::Up) {
+        selected.0 = (selected.0 + count - 1) % count;
+    }
+
+    if actions.just_pressed(Action::Down) {
+        selected.0 = (selected.0 + 1) % count;
+    }
+
+    if actions.just_pressed(Action::Confirm) && progress.is_unlocked(selected.0) {
+        commands.insert_resource(CampaignLevel(selected.0));
+        game_state.set(GameState::Loading);
+    }
+
+    if actions.just_pressed(Action::Back) {
+        game_state.set(GameState::Menu);
+    }
+}
+
+/// Shows the names and goals of the levels once they are loaded and highlights the selected
+/// level.
+fn update_rows(
+    levels: Res<CampaignLevels>,
+    assets: Res<Assets<LevelAsset>>,
+    progress: Res<CampaignProgress>,
+    selected: Res<SelectedCampaignLevel>,
+    mut rows: Query<(&LevelRow, &mut BackgroundColor)>,
+    mut texts: Query<(&LevelText, &ChildOf, &mut Text)>,
+) {
+    for (row, mut background_color) in rows.iter_mut() {
+        background_color.0 = if row.0 == selected.0 {
+            ROW_SELECTED_BACKGROUND_COLOR
+        } else {
+            ROW_BACKGROUND_COLOR
+        };
+    }
+
+    for (level_text, child_of, mut text) in texts.iter_mut() {
+        let Ok((row, _)) = rows.get(child_of.parent()) else {
+            continue;
+        };
+
+        let Some(level) = assets.get(&levels.0[row.0]) else {
+            continue;
+        };
+
+        let content = match level_text {
+            LevelText::Name => format!("{}. {}", row.0 + 1, level.0.name),
+            LevelText::Goal if progress.is_unlocked(row.0) => describe_goal(level.0.goal),
+            LevelText::Goal => "Locked".to_string(),
+        };
+
+        if text.0 != content {
+            text.0 = content;
+        }
+    }
+}
+
+/// Creates the UI of the level select screen and loads the levels of the campaign.
+fn setup_level_select(
+    mut commands: Commands,
+    progress: Res<CampaignProgress>,
+    mut selected: ResMut<SelectedCampaignLevel>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
+    selected.0 = progress.completed.min(CAMPAIGN.len() - 1);
+
+    commands.insert_resource(CampaignLevels(
+        CAMPAIGN
+            .iter()
+            .map(|&path| asset_server.load(path))
+            .collect(),
+    ));
+
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            OnLevelSelectScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Campaign"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(64.0),
+                            ..default()
+                        },
+                        TextColor(HEADLINE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for index in 0..CAMPAIGN.len() {
+                        let name_color = if progress.is_unlocked(index) {
+                            NAME_COLOR
+                        } else {
+                            LOCKED_COLOR
+                        };
+
+                        parent
+                            .spawn((
+                                Node {
+                                    width: Val::Px(720.0),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    padding: UiRect::horizontal(Val::Px(20.0)),
+                                    justify_content: JustifyContent::SpaceBetween,
+                                    ..default()
+                                },
+                                BackgroundColor(ROW_BACKGROUND_COLOR),
+                                LevelRow(index),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(format!("{}.", index + 1)),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(32.0),
+                                        ..default()
+                                    },
+                                    TextColor(name_color),
+                                    LevelText::Name,
+                                ));
+
+                                parent.spawn((
+                                    Text::default(),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(32.0),
+                                        ..default()
+                                    },
+                                    TextColor(TEXT_COLOR),
+                                    LevelText::Goal,
+                                ));
+                            });
+                    }
+
+                    parent.spawn((
+                        Text::new(format!(
+                            "Play with {}, go back with {}",
+                            settings.bindings.hint(Action::Confirm),
+                            settings.bindings.hint(Action::Back)
+                        )),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(24.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+
+    commands.init_resource::<InitialDelay>();
+}
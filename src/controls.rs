@@ -0,0 +1,716 @@
+use bevy::{
+    color::palettes::css::ANTIQUE_WHITE,
+    input::{InputSystems, gamepad::GamepadConnectionEvent},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::settings::{Settings, SettingsScreen};
+use crate::simulation::MAX_PLAYERS;
+
+use super::despawn_screen;
+
+/// Translates the input of the keyboard and all gamepads into [`Action`]s that can be queried
+/// with `Res<ButtonInput<Action>>`, and adds a screen to change the bindings.
+pub struct ControlsPlugin;
+
+/// The actions of every player in games with several players. The gamepad that was connected
+/// last belongs to the second player, the keyboard and all other gamepads to the first one.
+#[derive(Default, Resource)]
+pub struct PlayerActions(pub [ButtonInput<Action>; MAX_PLAYERS]);
+
+/// The connected gamepads in the order they were connected. A gamepad that is connected again
+/// keeps its entity, so the order of the entities doesn't tell which one came last.
+#[derive(Default, Resource)]
+pub struct ConnectedGamepads(Vec<Entity>);
+
+impl ConnectedGamepads {
+    pub fn all(&self) -> &[Entity] {
+        &self.0
+    }
+
+    /// The gamepads of every player in games with several players.
+    pub fn players(&self) -> [&[Entity]; MAX_PLAYERS] {
+        match self.0.split_last() {
+            Some((last, others)) => [others, std::slice::from_ref(last)],
+            None => [&[], &[]],
+        }
+    }
+}
+
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const VALUE_COLOR: Color = Color::WHITE;
+const ROW_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const ROW_SELECTED_BACKGROUND_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+
+/// Axes that can be bound to an action.
+const AXES: [GamepadAxis; 4] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+];
+
+#[derive(Component)]
+struct OnControlsScreen;
+
+/// Everything the player can do with the keyboard or a gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Pause,
+    Confirm,
+    Back,
+    /// Selects the next tool of the level editor.
+    NextTool,
+    /// Plays the edited level right away.
+    TestPlay,
+    /// Saves the edited level.
+    Save,
+    /// Changes the name of the edited level.
+    Rename,
+}
+
+impl Action {
+    const ALL: [Self; 11] = [
+        Self::Up,
+        Self::Down,
+        Self::Left,
+        Self::Right,
+        Self::Pause,
+        Self::Confirm,
+        Self::Back,
+        Self::NextTool,
+        Self::TestPlay,
+        Self::Save,
+        Self::Rename,
+    ];
+
+    /// Actions that are needed to navigate the menus, so they always keep at least one binding.
+    fn is_required(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::Left | Self::Right | Self::Confirm | Self::Back
+        )
+    }
+}
+
+/// A key, a button or the direction of an axis that triggers an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButton),
+    /// An axis that is tilted in the positive (`true`) or negative (`false`) direction.
+    Axis(GamepadAxis, bool),
+}
+
+impl Binding {
+    /// Returns `true` if the key or button is pressed or the axis is tilted beyond the dead zone.
+    fn is_pressed(
+        &self,
+        keyboard_input: Option<&ButtonInput<KeyCode>>,
+        gamepads: &[&Gamepad],
+        dead_zone: f32,
+    ) -> bool {
+        match *self {
+            Binding::Key(key) => keyboard_input.is_some_and(|keyboard| keyboard.pressed(key)),
+            Binding::Button(button) => gamepads.iter().any(|gamepad| gamepad.pressed(button)),
+            Binding::Axis(axis, positive) => gamepads.iter().any(|gamepad| {
+                gamepad.get(axis).is_some_and(|value| {
+                    if positive {
+                        value > dead_zone
+                    } else {
+                        value < -dead_zone
+                    }
+                })
+            }),
+        }
+    }
+
+    /// Short name that is shown on the controls screen.
+    fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => {
+                let name = format!("{:?}", key);
+                name.strip_prefix("Key")
+                    .or_else(|| name.strip_prefix("Digit"))
+                    .unwrap_or(&name)
+                    .to_string()
+            }
+            Binding::Button(button) => format!("{:?}", button),
+            Binding::Axis(axis, positive) => {
+                format!("{:?}{}", axis, if *positive { '+' } else { '-' })
+            }
+        }
+    }
+}
+
+/// The bindings of every action.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Bindings(BTreeMap<Action, Vec<Binding>>);
+
+/// Bindings as they are stored, which may lack actions that were added later.
+#[derive(Deserialize)]
+#[serde(rename = "Bindings")]
+struct StoredBindings(BTreeMap<Action, Vec<Binding>>);
+
+impl<'de> Deserialize<'de> for Bindings {
+    /// Actions that were added after the bindings were stored get their default bindings.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let StoredBindings(mut bindings) = StoredBindings::deserialize(deserializer)?;
+
+        for (action, defaults) in Bindings::default().0 {
+            bindings.entry(action).or_insert(defaults);
+        }
+
+        Ok(Self(bindings))
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+
+        Self(BTreeMap::from([
+            (
+                Action::Up,
+                vec![
+                    Key(KeyCode::ArrowUp),
+                    Key(KeyCode::KeyW),
+                    Key(KeyCode::KeyK),
+                    Button(GamepadButton::DPadUp),
+                    Axis(GamepadAxis::LeftStickY, true),
+                    Axis(GamepadAxis::RightStickY, true),
+                ],
+            ),
+            (
+                Action::Down,
+                vec![
+                    Key(KeyCode::ArrowDown),
+                    Key(KeyCode::KeyS),
+                    Key(KeyCode::KeyJ),
+                    Button(GamepadButton::DPadDown),
+                    Axis(GamepadAxis::LeftStickY, false),
+                    Axis(GamepadAxis::RightStickY, false),
+                ],
+            ),
+            (
+                Action::Left,
+                vec![
+                    Key(KeyCode::ArrowLeft),
+                    Key(KeyCode::KeyA),
+                    Key(KeyCode::KeyH),
+                    Button(GamepadButton::DPadLeft),
+                    Axis(GamepadAxis::LeftStickX, false),
+                    Axis(GamepadAxis::RightStickX, false),
+                ],
+            ),
+            (
+                Action::Right,
+                vec![
+                    Key(KeyCode::ArrowRight),
+                    Key(KeyCode::KeyD),
+                    Key(KeyCode::KeyL),
+                    Button(GamepadButton::DPadRight),
+                    Axis(GamepadAxis::LeftStickX, true),
+                    Axis(GamepadAxis::RightStickX, true),
+                ],
+            ),
+            (
+                Action::Pause,
+                vec![
+                    Key(KeyCode::Space),
+                    Key(KeyCode::KeyP),
+                    Button(GamepadButton::Start),
+                ],
+            ),
+            (
+                Action::Confirm,
+                vec![
+                    Key(KeyCode::Enter),
+                    Key(KeyCode::Space),
+                    Button(GamepadButton::South),
+                ],
+            ),
+            (
+                Action::Back,
+                vec![
+                    Key(KeyCode::Escape),
+                    Key(KeyCode::Backspace),
+                    Button(GamepadButton::East),
+                ],
+            ),
+            (
+                Action::NextTool,
+                vec![Key(KeyCode::Tab), Button(GamepadButton::West)],
+            ),
+            (
+                Action::TestPlay,
+                vec![Key(KeyCode::KeyT), Button(GamepadButton::North)],
+            ),
+            (
+                Action::Save,
+                vec![Key(KeyCode::F2), Button(GamepadButton::Select)],
+            ),
+            (Action::Rename, vec![Key(KeyCode::F3)]),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Name of the first binding of an action for hints like "go back with Escape", or the
+    /// name of the action if nothing is bound to it.
+    pub fn hint(&self, action: Action) -> String {
+        self.get(action)
+            .first()
+            .map_or_else(|| format!("{:?}", action), Binding::name)
+    }
+
+    /// Returns `true` if the binding stops waiting for a new binding of the action instead of
+    /// being added or removed. That is the case for the bindings of back, unless they are
+    /// changed themselves.
+    fn cancels(&self, action: Action, binding: Binding) -> bool {
+        action != Action::Back && self.get(Action::Back).contains(&binding)
+    }
+
+    /// Adds the binding to the action or removes it, if the action was already bound to it. The
+    /// last binding of a required action is not removed.
+    fn toggle(&mut self, action: Action, binding: Binding) {
+        let bindings = self.0.entry(action).or_default();
+
+        if let Some(index) = bindings.iter().position(|&existing| existing == binding) {
+            if bindings.len() > 1 || !action.is_required() {
+                bindings.remove(index);
+            }
+        } else {
+            bindings.push(binding);
+        }
+    }
+}
+
+/// Row of the controls screen that is selected and whether the next input is bound to its action.
+/// The row after all actions resets the bindings.
+#[derive(Default, Resource)]
+struct SelectedRow {
+    index: usize,
+    listening: bool,
+}
+
+/// Displays the bindings of an action.
+#[derive(Component)]
+struct BindingsText(Action);
+
+#[derive(Component)]
+struct ResetRow;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (track_connections, update_actions)
+                .chain()
+                .after(InputSystems),
+        )
+        .add_systems(OnEnter(SettingsScreen::Controls), setup_controls)
+        .add_systems(
+            Update,
+            (edit_bindings, update_rows)
+                .chain()
+                .run_if(in_state(SettingsScreen::Controls)),
+        )
+        .add_systems(
+            OnExit(SettingsScreen::Controls),
+            despawn_screen::<OnControlsScreen>,
+        )
+        .init_resource::<ButtonInput<Action>>()
+        .init_resource::<PlayerActions>()
+        .init_resource::<ConnectedGamepads>()
+        .init_resource::<SelectedRow>();
+    }
+}
+
+fn track_connections(
+    mut connection_reader: MessageReader<GamepadConnectionEvent>,
+    mut connected: ResMut<ConnectedGamepads>,
+) {
+    for event in connection_reader.read() {
+        connected.0.retain(|&gamepad| gamepad != event.gamepad);
+
+        if event.connected() {
+            connected.0.push(event.gamepad);
+        }
+    }
+}
+
+/// Presses every action that has at least one pressed binding and releases all other actions,
+/// for all devices together and for the devices of each player.
+fn update_actions(
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut player_actions: ResMut<PlayerActions>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    connected: Res<ConnectedGamepads>,
+    gamepads: Query<&Gamepad>,
+    settings: Res<Settings>,
+) {
+    let gamepads = |entities: &[Entity]| -> Vec<&Gamepad> {
+        entities
+            .iter()
+            .filter_map(|&gamepad| gamepads.get(gamepad).ok())
+            .collect()
+    };
+
+    let [first, second] = connected.players().map(gamepads);
+    let [first_actions, second_actions] = &mut player_actions.0;
+
+    update_device_actions(
+        &mut actions,
+        Some(&keyboard_input),
+        &gamepads(connected.all()),
+        &settings,
+    );
+    update_device_actions(first_actions, Some(&keyboard_input), &first, &settings);
+    update_device_actions(second_actions, None, &second, &settings);
+}
+
+fn update_device_actions(
+    actions: &mut ButtonInput<Action>,
+    keyboard_input: Option<&ButtonInput<KeyCode>>,
+    gamepads: &[&Gamepad],
+    settings: &Settings,
+) {
+    actions.clear();
+
+    for action in Action::ALL {
+        if settings
+            .bindings
+            .get(action)
+            .iter()
+            .any(|binding| binding.is_pressed(keyboard_input, gamepads, settings.dead_zone()))
+        {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+    }
+}
+
+/// Returns the first key, button or axis that was pressed during this frame. An axis only counts
+/// if it was within the dead zone during the previous frame, so that a stick that rests or
+/// drifts outside of it is not bound.
+fn just_pressed_binding(
+    keyboard_input: &ButtonInput<KeyCode>,
+    gamepads: &Query<(Entity, &Gamepad)>,
+    previous_axes: &HashMap<(Entity, GamepadAxis), f32>,
+    dead_zone: f32,
+) -> Option<Binding> {
+    if let Some(&key) = keyboard_input.get_just_pressed().next() {
+        return Some(Binding::Key(key));
+    }
+
+    for (entity, gamepad) in gamepads.iter() {
+        if let Some(&button) = gamepad.get_just_pressed().next() {
+            return Some(Binding::Button(button));
+        }
+
+        for axis in AXES {
+            if let Some(value) = gamepad.get(axis)
+                && value.abs() > dead_zone
+                && previous_axes
+                    .get(&(entity, axis))
+                    .is_some_and(|previous| previous.abs() <= dead_zone)
+            {
+                return Some(Binding::Axis(axis, value > 0.0));
+            }
+        }
+    }
+
+    None
+}
+
+/// Selects rows with up and down. Confirming an action waits for the next key, button or axis,
+/// which is added to the action or removed from it if it was already bound to it. The bindings of
+/// [`Action::Back`] stop waiting without a change, unless the bindings of back are changed.
+fn edit_bindings(
+    actions: Res<ButtonInput<Action>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<(Entity, &Gamepad)>,
+    mut selected: ResMut<SelectedRow>,
+    mut settings: ResMut<Settings>,
+    mut settings_screen: ResMut<NextState<SettingsScreen>>,
+    mut previous_axes: Local<HashMap<(Entity, GamepadAxis), f32>>,
+) {
+    let binding = just_pressed_binding(
+        &keyboard_input,
+        &gamepads,
+        &previous_axes,
+        settings.dead_zone(),
+    );
+
+    previous_axes.clear();
+
+    for (entity, gamepad) in gamepads.iter() {
+        for axis in AXES {
+            if let Some(value) = gamepad.get(axis) {
+                previous_axes.insert((entity, axis), value);
+            }
+        }
+    }
+
+    let rows = Action::ALL.len() + 1;
+
+    if selected.listening {
+        if let Some(binding) = binding {
+            let action = Action::ALL[selected.index];
+
+            if !settings.bindings.cancels(action, binding) {
+                settings.bindings.toggle(action, binding);
+            }
+
+            selected.listening = false;
+        }
+
+        return;
+    }
+
+    if actions.just_pressed(Action::Up) {
+        selected.index = (selected.index + rows - 1) % rows;
+    }
+
+    if actions.just_pressed(Action::Down) {
+        selected.index = (selected.index + 1) % rows;
+    }
+
+    if actions.just_pressed(Action::Confirm) {
+        if selected.index < Action::ALL.len() {
+            selected.listening = true;
+        } else {
+            settings.bindings = Bindings::default();
+        }
+    }
+
+    if actions.just_pressed(Action::Back) {
+        settings_screen.set(SettingsScreen::Settings);
+    }
+}
+
+fn update_rows(
+    settings: Res<Settings>,
+    selected: Res<SelectedRow>,
+    mut texts: Query<(&BindingsText, &mut Text, &ChildOf)>,
+    mut rows: Query<&mut BackgroundColor, Without<ResetRow>>,
+    mut reset_rows: Query<&mut BackgroundColor, With<ResetRow>>,
+) {
+    if !settings.is_changed() && !selected.is_changed() {
+        return;
+    }
+
+    for (bindings_text, mut text, child_of) in texts.iter_mut() {
+        text.0 = bindings_text_value(&settings, &selected, bindings_text.0);
+
+        if let Ok(mut background_color) = rows.get_mut(child_of.parent()) {
+            background_color.0 = row_background_color(&selected, Some(bindings_text.0));
+        }
+    }
+
+    for mut background_color in reset_rows.iter_mut() {
+        background_color.0 = row_background_color(&selected, None);
+    }
+}
+
+fn bindings_text_value(settings: &Settings, selected: &SelectedRow, action: Action) -> String {
+    if selected.listening && Action::ALL[selected.index] == action {
+        return "Press a key or button".to_string();
+    }
+
+    settings
+        .bindings
+        .get(action)
+        .iter()
+        .map(Binding::name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the background color of the row of the given action or of the reset row (`None`).
+fn row_background_color(selected: &SelectedRow, action: Option<Action>) -> Color {
+    if Action::ALL.get(selected.index).copied() == action {
+        ROW_SELECTED_BACKGROUND_COLOR
+    } else {
+        ROW_BACKGROUND_COLOR
+    }
+}
+
+/// Creates the UI of the controls screen.
+fn setup_controls(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut selected: ResMut<SelectedRow>,
+    asset_server: Res<AssetServer>,
+) {
+    selected.listening = false;
+
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    let row_node = Node {
+        width: Val::Px(960.0),
+        margin: UiRect::all(Val::Px(4.0)),
+        padding: UiRect::horizontal(Val::Px(20.0)),
+        justify_content: JustifyContent::SpaceBetween,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            // covers the menu or the paused game underneath
+            BackgroundColor(Color::BLACK),
+            GlobalZIndex(1),
+            OnControlsScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Controls"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(64.0),
+                            ..default()
+                        },
+                        TextColor(HEADLINE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for action in Action::ALL {
+                        parent
+                            .spawn((
+                                row_node.clone(),
+                                BackgroundColor(row_background_color(&selected, Some(action))),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(format!("{:?}", action)),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(24.0),
+                                        ..default()
+                                    },
+                                    TextColor(TEXT_COLOR),
+                                ));
+
+                                parent.spawn((
+                                    Text::new(bindings_text_value(&settings, &selected, action)),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(24.0),
+                                        ..default()
+                                    },
+                                    TextColor(VALUE_COLOR),
+                                    BindingsText(action),
+                                ));
+                            });
+                    }
+
+                    parent
+                        .spawn((
+                            row_node.clone(),
+                            BackgroundColor(row_background_color(&selected, None)),
+                            ResetRow,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Reset to defaults"),
+                                TextFont {
+                                    font: font.clone(),
+                                    font_size: FontSize::Px(24.0),
+                                    ..default()
+                                },
+                                TextColor(TEXT_COLOR),
+                            ));
+                        });
+
+                    parent.spawn((
+                        Text::new(
+                            "Confirm an action and press a key or button to add or remove it\n\
+                             Back cancels unless its own bindings are changed, the last binding \
+                             of a direction, Confirm or Back is kept",
+                        ),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(24.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_bindings_get_defaults_for_new_actions() {
+        let mut stored = Bindings::default();
+        stored.0.remove(&Action::Rename);
+        stored
+            .0
+            .insert(Action::Up, vec![Binding::Key(KeyCode::KeyI)]);
+
+        let bindings: Bindings = ron::from_str(&ron::to_string(&stored).unwrap()).unwrap();
+
+        assert_eq!(bindings.get(Action::Up), [Binding::Key(KeyCode::KeyI)]);
+        assert_eq!(
+            bindings.get(Action::Rename),
+            Bindings::default().get(Action::Rename)
+        );
+    }
+
+    #[test]
+    fn bindings_of_back_cancel_unless_back_is_changed() {
+        let mut bindings = Bindings::default();
+        let escape = Binding::Key(KeyCode::Escape);
+        let east = Binding::Button(GamepadButton::East);
+
+        assert!(bindings.cancels(Action::Pause, escape));
+        assert!(bindings.cancels(Action::Rename, east));
+        assert!(!bindings.cancels(Action::Back, escape));
+
+        bindings.toggle(Action::Back, escape);
+
+        assert!(!bindings.get(Action::Back).contains(&escape));
+        assert!(!bindings.cancels(Action::Pause, escape));
+
+        bindings.toggle(Action::Pause, escape);
+
+        assert!(bindings.get(Action::Pause).contains(&escape));
+    }
+}
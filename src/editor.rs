@@ -0,0 +1,646 @@
+use anyhow::{Context, Result};
+use bevy::input::ButtonState;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::controls::Action;
+use crate::game::{Arena, DiplopodColors, GoalReached};
+use crate::level::{LevelAsset, USER_SOURCE};
+use crate::save::{PERSISTENT, SaveFailed, data_dir, write_atomically};
+use crate::settings::Settings;
+use crate::simulation::Position;
+use crate::simulation::level::{Level, start_tile};
+
+use super::{GameState, despawn_screen};
+
+/// Adds an editor to create levels, test them right away and save them to the data directory,
+/// from where they can be played and shared.
+pub struct EditorPlugin;
+
+/// Directory of the levels within the data directory.
+const LEVEL_DIRECTORY: &str = "levels";
+const LEVEL_EXTENSION: &str = "level";
+
+/// Maximum number of characters of the name of a level.
+const MAX_NAME_LENGTH: usize = 24;
+
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const STATUS_COLOR: Color = Color::WHITE;
+const CURSOR_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.35);
+const START_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
+const SECOND_START_COLOR: Color = Color::srgb(0.55, 0.55, 0.9);
+const RADIUS_FACTOR: f32 = 0.9;
+const FILL_RADIUS_FACTOR: f32 = 0.7;
+
+/// The level in the editor. It is kept while testing it and after leaving the editor.
+#[derive(Resource)]
+struct EditedLevel(Level);
+
+/// The file the edited level was read from or saved to. Another file with the name of the level
+/// is only overwritten if the level is saved twice.
+#[derive(Default, Resource)]
+struct EditedFile {
+    path: Option<PathBuf>,
+    overwrite: Option<PathBuf>,
+}
+
+/// The name of the level is typed while this resource exists.
+#[derive(Resource)]
+struct Renaming;
+
+/// What is placed at the cursor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tool {
+    Wall,
+    Food,
+    Poison,
+    Start,
+    /// The start of the second player in the modes for two players.
+    SecondStart,
+    Erase,
+}
+
+impl Tool {
+    const ALL: [Self; 6] = [
+        Self::Wall,
+        Self::Food,
+        Self::Poison,
+        Self::Start,
+        Self::SecondStart,
+        Self::Erase,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Wall => "Wall",
+            Tool::Food => "Food",
+            Tool::Poison => "Poison",
+            Tool::Start => "Start",
+            Tool::SecondStart => "Second start",
+            Tool::Erase => "Erase",
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Cursor {
+    position: Position,
+    tool: usize,
+}
+
+/// The result of the last action of the editor, e.g. where the level was saved.
+#[derive(Default, Resource)]
+struct EditorStatus(String);
+
+/// The level that is tested from the editor. After the game the player returns to the editor.
+#[derive(Resource)]
+pub struct TestPlay {
+    pub path: String,
+    pub handle: Handle<LevelAsset>,
+}
+
+#[derive(Component)]
+struct OnEditorScreen;
+
+/// Shows a wall, consumable or the start of the edited level.
+#[derive(Component)]
+struct EditorTile;
+
+#[derive(Component)]
+struct EditorCursor;
+
+#[derive(Component)]
+struct StatusText;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Editor), setup_editor)
+            .add_systems(
+                Update,
+                (
+                    (
+                        edit.run_if(not(resource_exists::<Renaming>)),
+                        rename.run_if(resource_exists::<Renaming>),
+                        draw_level.run_if(resource_changed::<EditedLevel>),
+                        (move_cursor, update_status).run_if(
+                            resource_changed::<Cursor>
+                                .or_else(resource_changed::<EditorStatus>)
+                                .or_else(resource_changed::<EditedLevel>),
+                        ),
+                    )
+                        .chain()
+                        .run_if(in_state(GameState::Editor)),
+                    finish_test_play
+                        .run_if(on_message::<GoalReached>)
+                        .run_if(resource_exists::<TestPlay>),
+                ),
+            )
+            .add_systems(
+                OnExit(GameState::Editor),
+                (despawn_screen::<OnEditorScreen>, stop_renaming),
+            )
+            .add_systems(OnEnter(GameState::Menu), stop_test_play)
+            .init_resource::<EditedFile>();
+    }
+}
+
+/// Name of the file a level is saved to, which is derived from the name of the level (e.g.
+/// `my-maze.level` for "My maze").
+fn file_name(name: &str) -> String {
+    let stem: String = name
+        .trim()
+        .chars()
+        .filter_map(|character| match character {
+            'a'..='z' | '0'..='9' => Some(character),
+            'A'..='Z' => Some(character.to_ascii_lowercase()),
+            ' ' | '-' => Some('-'),
+            _ => None,
+        })
+        .collect();
+
+    if stem.is_empty() {
+        format!("custom.{}", LEVEL_EXTENSION)
+    } else {
+        format!("{}.{}", stem, LEVEL_EXTENSION)
+    }
+}
+
+/// Reads the level that was saved last and returns it with its path. Without a saved level the
+/// editor starts with an empty bordered arena.
+fn read_level(settings: &Settings) -> Result<(Level, Option<PathBuf>)> {
+    if !PERSISTENT {
+        return Ok((new_level(settings), None));
+    }
+
+    let directory = data_dir()?.join(LEVEL_DIRECTORY);
+
+    let entries = match fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((new_level(settings), None)),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Unable to read {}", directory.display()));
+        }
+    };
+
+    let latest = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == LEVEL_EXTENSION))
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max();
+
+    let Some((_, path)) = latest else {
+        return Ok((new_level(settings), None));
+    };
+
+    let level = fs::read_to_string(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| contents.parse())
+        .with_context(|| format!("Unable to read level {}", path.display()))?;
+
+    Ok((level, Some(path)))
+}
+
+/// An empty bordered arena of the size from the settings.
+fn new_level(settings: &Settings) -> Level {
+    Level {
+        name: "Custom".to_string(),
+        ..Level::bordered(settings.arena)
+    }
+}
+
+/// Saves the level to a file named after it in the data directory. Another level that was saved
+/// with the same name is only overwritten if this is repeated. Returns the status to show.
+fn write_level(level: &Level, file: &mut EditedFile) -> Result<String> {
+    let directory = data_dir()?.join(LEVEL_DIRECTORY);
+    let path = directory.join(file_name(&level.name));
+
+    if path.exists() && file.path.as_ref() != Some(&path) && file.overwrite.as_ref() != Some(&path)
+    {
+        let status = format!("{} exists, save again to overwrite it", path.display());
+        file.overwrite = Some(path);
+        return Ok(status);
+    }
+
+    fs::create_dir_all(&directory)
+        .with_context(|| format!("Unable to create {}", directory.display()))?;
+
+    write_atomically(&path, &level.to_string())?;
+
+    let status = format!("Saved to {}", path.display());
+    file.path = Some(path);
+    file.overwrite = None;
+
+    Ok(status)
+}
+
+/// Places the tool at the position. The starts can neither be covered nor moved into a wall,
+/// only the second start can be erased. Returns `true` if the level was changed.
+fn apply_tool(level: &mut Level, tool: Tool, position: Position) -> bool {
+    let is_first_start = Position::from(level.start) == position;
+    let is_second_start = level.second_start.map(Position::from) == Some(position);
+    let is_start = is_first_start || is_second_start;
+    let is_wall = level.walls.contains(&position);
+
+    let (wall, food, poison) = match tool {
+        Tool::Wall if is_start => return false,
+        Tool::Food | Tool::Poison if is_start => return false,
+        Tool::Start if is_wall || is_second_start => return false,
+        Tool::SecondStart if is_wall || is_first_start => return false,
+        Tool::Erase if is_first_start => return false,
+        Tool::Wall => (true, false, false),
+        Tool::Food => (false, true, false),
+        Tool::Poison => (false, false, true),
+        Tool::Start => {
+            level.start = start_tile(position);
+            level.food.retain(|&p| p != position);
+            level.poison.retain(|&p| p != position);
+            return !is_first_start;
+        }
+        Tool::SecondStart => {
+            level.second_start = Some(start_tile(position));
+            level.food.retain(|&p| p != position);
+            level.poison.retain(|&p| p != position);
+            return !is_second_start;
+        }
+        Tool::Erase if is_second_start => {
+            level.second_start = None;
+            return true;
+        }
+        Tool::Erase => (false, false, false),
+    };
+
+    let before = (
+        is_wall,
+        level.food.contains(&position),
+        level.poison.contains(&position),
+    );
+
+    if before == (wall, food, poison) {
+        return false;
+    }
+
+    level.walls.retain(|&p| p != position);
+    level.food.retain(|&p| p != position);
+    level.poison.retain(|&p| p != position);
+
+    if wall {
+        level.walls.push(position);
+    }
+    if food {
+        level.food.push(position);
+    }
+    if poison {
+        level.poison.push(position);
+    }
+
+    true
+}
+
+fn setup_editor(
+    mut commands: Commands,
+    edited: Option<Res<EditedLevel>>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
+    mut save_failed_writer: MessageWriter<SaveFailed>,
+) {
+    commands.remove_resource::<TestPlay>();
+
+    let level = match edited {
+        Some(edited) => edited.0.clone(),
+        None => {
+            let (level, path) = read_level(&settings).unwrap_or_else(|e| {
+                save_failed_writer.write(SaveFailed(format!("{:#}", e)));
+                (new_level(&settings), None)
+            });
+
+            commands.insert_resource(EditedFile {
+                path,
+                overwrite: None,
+            });
+
+            level
+        }
+    };
+
+    let arena = Arena::new(&level);
+
+    commands.insert_resource(Cursor {
+        position: Position::from(level.start),
+        tool: 0,
+    });
+    commands.insert_resource(EditorStatus::default());
+
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::new(
+            arena.tile_size() * 2.0,
+            arena.tile_size() * 2.0,
+        ))),
+        MeshMaterial2d(materials.add(CURSOR_COLOR)),
+        Transform::default(),
+        EditorCursor,
+        OnEditorScreen,
+    ));
+
+    commands.insert_resource(arena);
+    // inserted again to draw the level
+    commands.insert_resource(EditedLevel(level));
+
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                padding: UiRect::axes(Val::Px(20.0), Val::Px(5.0)),
+                justify_content: JustifyContent::SpaceBetween,
+                ..default()
+            },
+            OnEditorScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::default(),
+                TextFont {
+                    font: font.clone(),
+                    font_size: FontSize::Px(24.0),
+                    ..default()
+                },
+                TextColor(STATUS_COLOR),
+                StatusText,
+            ));
+
+            parent.spawn((
+                Text::new(format!(
+                    "{} place, {} tool, {} name, {} test, {} save, {} back",
+                    settings.bindings.hint(Action::Confirm),
+                    settings.bindings.hint(Action::NextTool),
+                    settings.bindings.hint(Action::Rename),
+                    settings.bindings.hint(Action::TestPlay),
+                    settings.bindings.hint(Action::Save),
+                    settings.bindings.hint(Action::Back)
+                )),
+                TextFont {
+                    font: font.clone(),
+                    font_size: FontSize::Px(24.0),
+                    ..default()
+                },
+                TextColor(TEXT_COLOR),
+            ));
+        });
+}
+
+/// Moves the cursor and changes the level. Holding the confirm action while moving draws a
+/// line with the tool.
+#[allow(clippy::too_many_arguments)]
+fn edit(
+    mut commands: Commands,
+    actions: Res<ButtonInput<Action>>,
+    mut edited: ResMut<EditedLevel>,
+    mut file: ResMut<EditedFile>,
+    mut cursor: ResMut<Cursor>,
+    mut status: ResMut<EditorStatus>,
+    mut levels: ResMut<Assets<LevelAsset>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut save_failed_writer: MessageWriter<SaveFailed>,
+) {
+    if actions.just_pressed(Action::Back) {
+        game_state.set(GameState::Menu);
+        return;
+    }
+
+    if actions.just_pressed(Action::Rename) {
+        commands.insert_resource(Renaming);
+        status.0 = "Type the name, Enter to finish".to_string();
+        return;
+    }
+
+    if actions.just_pressed(Action::TestPlay) {
+        commands.insert_resource(TestPlay {
+            path: format!(
+                "{}://{}/{}",
+                USER_SOURCE,
+                LEVEL_DIRECTORY,
+                file_name(&edited.0.name)
+            ),
+            handle: levels.add(LevelAsset(edited.0.clone())),
+        });
+        game_state.set(GameState::Loading);
+        return;
+    }
+
+    if actions.just_pressed(Action::Save) && !PERSISTENT {
+        status.0 = "Levels can't be saved in the browser".to_string();
+    } else if actions.just_pressed(Action::Save) {
+        status.0 = match write_level(&edited.0, &mut file) {
+            Ok(status) => status,
+            Err(e) => {
+                save_failed_writer.write(SaveFailed(format!("{:#}", e)));
+                "Unable to save the level".to_string()
+            }
+        };
+    }
+
+    if actions.just_pressed(Action::NextTool) {
+        cursor.tool = (cursor.tool + 1) % Tool::ALL.len();
+    }
+
+    let mut position = cursor.position;
+
+    if actions.just_pressed(Action::Up) {
+        position.y += 1;
+    }
+    if actions.just_pressed(Action::Down) {
+        position.y -= 1;
+    }
+    if actions.just_pressed(Action::Left) {
+        position.x -= 1;
+    }
+    if actions.just_pressed(Action::Right) {
+        position.x += 1;
+    }
+
+    // the outer walls can't be changed, levels that wrap around have none
+    let border = i32::from(!edited.0.wrap_around);
+    position.x = position.x.clamp(border, edited.0.width - border);
+    position.y = position.y.clamp(border, edited.0.height - border);
+
+    let moved = position != cursor.position;
+
+    if moved {
+        cursor.position = position;
+    }
+
+    if actions.just_pressed(Action::Confirm) || (moved && actions.pressed(Action::Confirm)) {
+        let tool = Tool::ALL[cursor.tool];
+
+        if apply_tool(&mut edited.bypass_change_detection().0, tool, position) {
+            edited.set_changed();
+        }
+    }
+}
+
+/// Changes the name of the level, which is also the name of its file, with the keyboard until
+/// Enter or Escape is pressed.
+fn rename(
+    mut commands: Commands,
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut edited: ResMut<EditedLevel>,
+    mut status: ResMut<EditorStatus>,
+) {
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        let name = &mut edited.0.name;
+
+        match &event.logical_key {
+            Key::Character(text) => {
+                for character in text
+                    .chars()
+                    .filter(|&c| c.is_ascii_alphanumeric() || c == ' ' || c == '-')
+                {
+                    if name.len() < MAX_NAME_LENGTH {
+                        name.push(character);
+                    }
+                }
+            }
+            Key::Space if name.len() < MAX_NAME_LENGTH => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Enter | Key::Escape => {
+                status.0 = format!("Saving as {}", file_name(name));
+                commands.remove_resource::<Renaming>();
+                return;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn stop_renaming(mut commands: Commands) {
+    commands.remove_resource::<Renaming>();
+}
+
+/// Shows the walls, consumables and the starts of the level.
+fn draw_level(
+    mut commands: Commands,
+    edited: Res<EditedLevel>,
+    arena: Res<Arena>,
+    colors: Res<DiplopodColors>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    tiles: Query<Entity, With<EditorTile>>,
+) {
+    for entity in tiles.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let level = &edited.0;
+    let size = arena.tile_size();
+    let square = meshes.add(Rectangle::new(size * 2.0, size * 2.0));
+    let circle = meshes.add(Circle::new(size * RADIUS_FACTOR));
+    let fill = meshes.add(Circle::new(size * RADIUS_FACTOR * FILL_RADIUS_FACTOR));
+
+    for &position in &level.walls {
+        commands.spawn((
+            Mesh2d(square.clone()),
+            colors.wall.clone(),
+            arena.position_transform(position),
+            EditorTile,
+            OnEditorScreen,
+        ));
+    }
+
+    for &position in &level.food {
+        commands.spawn((
+            Mesh2d(circle.clone()),
+            colors.food.clone(),
+            arena.position_transform(position),
+            EditorTile,
+            OnEditorScreen,
+        ));
+    }
+
+    for &position in &level.poison {
+        commands
+            .spawn((
+                Mesh2d(circle.clone()),
+                colors.poison_outline.clone(),
+                arena.position_transform(position),
+                EditorTile,
+                OnEditorScreen,
+            ))
+            .with_child((
+                Mesh2d(fill.clone()),
+                colors.poison_fill.clone(),
+                Transform::from_translation(Vec3::new(0.0, 0.0, 1.0)),
+            ));
+    }
+
+    let start = meshes.add(Rectangle::new(size, size));
+
+    commands.spawn((
+        Mesh2d(start.clone()),
+        MeshMaterial2d(materials.add(START_COLOR)),
+        arena.tile_transform(level.start),
+        EditorTile,
+        OnEditorScreen,
+    ));
+
+    if let Some(second_start) = level.second_start {
+        commands.spawn((
+            Mesh2d(start),
+            MeshMaterial2d(materials.add(SECOND_START_COLOR)),
+            arena.tile_transform(second_start),
+            EditorTile,
+            OnEditorScreen,
+        ));
+    }
+}
+
+fn move_cursor(
+    cursor: Res<Cursor>,
+    arena: Res<Arena>,
+    mut transforms: Query<&mut Transform, With<EditorCursor>>,
+) {
+    for mut transform in transforms.iter_mut() {
+        *transform = arena.position_transform(cursor.position);
+        // above the walls and consumables
+        transform.translation.z = 3.0;
+    }
+}
+
+fn update_status(
+    cursor: Res<Cursor>,
+    status: Res<EditorStatus>,
+    edited: Res<EditedLevel>,
+    mut texts: Query<&mut Text, With<StatusText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.0 = format!(
+            "{}  Tool {}  {}, {}  {}",
+            edited.0.name,
+            Tool::ALL[cursor.tool].label(),
+            cursor.position.x,
+            cursor.position.y,
+            status.0
+        );
+    }
+}
+
+/// Returns to the editor once the goal of the tested level is reached.
+fn finish_test_play(mut game_state: ResMut<NextState<GameState>>) {
+    game_state.set(GameState::Editor);
+}
+
+fn stop_test_play(mut commands: Commands) {
+    commands.remove_resource::<TestPlay>();
+}
@@ -1,130 +1,159 @@
 pub mod antidote;
+pub mod attract;
 pub mod diplopod;
 pub mod fading_text;
 pub mod food;
+pub mod hud;
 pub mod poison;
 pub mod superfood;
 pub mod wall;
 
 use crate::GameState;
-use crate::MAX_X;
 use crate::MAX_Y;
 use crate::Sounds;
+use crate::campaign::CampaignLevel;
+use crate::controls::ConnectedGamepads;
 use crate::despawn_screen;
-use crate::highscore::Highscore;
+use crate::editor::TestPlay;
 use crate::highscore::Lastscore;
+use crate::highscore::Leaderboard;
+use crate::level::{LevelAsset, LevelHandle};
+use crate::network::{self, NetworkGame};
+use crate::pause::PauseState;
+use crate::replay::Playback;
+use crate::save::Statistics;
+use crate::settings::Settings;
+use crate::simulation;
+use crate::simulation::CONSUMABLE_SCALE_FACTOR;
+use crate::simulation::Direction;
+use crate::simulation::Kind;
+use crate::simulation::MAX_PLAYERS;
+use crate::simulation::Mode;
+use crate::simulation::Position;
+use crate::simulation::Rules;
+use crate::simulation::Simulation;
+use crate::simulation::Tile;
+use crate::simulation::computer;
+use crate::simulation::level::Level;
 use antidote::*;
-use bevy::color::palettes::css::BLUE;
-use bevy::color::palettes::css::ORANGE;
-use bevy::color::palettes::css::RED;
+use attract::Attract;
+use bevy::camera::ScalingMode;
 use bevy::ecs::system::SystemState;
-use bevy::input::common_conditions::input_just_pressed;
 use bevy::input::gamepad::GamepadRumbleIntensity;
 use bevy::input::gamepad::GamepadRumbleRequest;
 use bevy::prelude::*;
-use bevy::time::common_conditions::on_timer;
 use diplopod::*;
 use fading_text::SpawnFadingText;
 use food::SpawnFood;
 use poison::SpawnPoison;
-use rand::Rng;
-use rand::rng;
-use rand::seq::SliceRandom;
 use std::time::Duration;
 use superfood::*;
 use wall::SpawnWall;
 
-const CONSUMABLE_WIDTH: i32 = 39 + 1;
-const CONSUMABLE_HEIGHT: i32 = 21 + 1;
-const CONSUMABLE_SCALE_FACTOR: i32 = 2;
-const ARENA_WIDTH: i32 = (CONSUMABLE_WIDTH + 1) * CONSUMABLE_SCALE_FACTOR;
-const ARENA_HEIGHT: i32 = (CONSUMABLE_HEIGHT + 1) * CONSUMABLE_SCALE_FACTOR;
-const TILE_SIZE: f32 = MAX_X / ARENA_WIDTH as f32;
-const UPPER_LEFT: Vec2 = Vec2::new(
-    (MAX_X - (ARENA_WIDTH - 1) as f32 * TILE_SIZE) / 2.,
-    (MAX_Y - (ARENA_HEIGHT - 1) as f32 * TILE_SIZE) / 2.,
-);
+/// Height of the arena on the screen, which leaves room for the HUD above it.
+const ARENA_SCREEN_HEIGHT: f32 = 1077.0;
 const RADIUS_FACTOR: f32 = 0.9;
 
-const AMOUNT_OF_FOOD: u32 = 16;
-const AMOUNT_OF_POISON: u32 = 17;
-const SPECIAL_SPAWN_INTERVAL: u32 = 16;
-
-pub const ANTIDOTE_COLOR: Color = Color::WHITE;
-pub const DIPLOPOD_COLOR: Color = Color::Srgba(ORANGE);
-pub const DIPLOPOD_IMMUNE_COLOR: Color = Color::WHITE;
-pub const FOOD_COLOR: Color = Color::srgb(0.0, 1.0, 0.0);
-pub const POISON_FILL_COLOR: Color = Color::BLACK;
-pub const POISON_OUTLINE_COLOR: Color = Color::Srgba(RED);
-pub const SUPERFOOD_COLOR: Color = Color::Srgba(BLUE);
-pub const WALL_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
-
-#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
-pub struct Position {
-    pub x: i32,
-    pub y: i32,
-}
-
 type CommandResources<'a> = SystemState<(
     Commands<'a, 'a>,
     ResMut<'a, Assets<Mesh>>,
     Res<'a, DiplopodColors>,
+    Res<'a, Arena>,
 )>;
 
-impl From<Position> for Transform {
-    fn from(position: Position) -> Self {
-        Transform::from_xyz(
-            position.x as f32 * TILE_SIZE * CONSUMABLE_SCALE_FACTOR as f32 + UPPER_LEFT.x
-                - MAX_X / 2.
-                + TILE_SIZE / 2.,
-            position.y as f32 * TILE_SIZE * CONSUMABLE_SCALE_FACTOR as f32 + UPPER_LEFT.y
-                - MAX_Y / 2.
-                + TILE_SIZE / 2.,
+/// Layout of the arena of the current game on the screen. The height of the arena is the same
+/// for every size, the width follows its aspect ratio.
+#[derive(Resource)]
+pub struct Arena {
+    tile_width: i32,
+    tile_height: i32,
+    tile_size: f32,
+}
+
+impl Arena {
+    pub fn new(level: &Level) -> Self {
+        Self {
+            tile_width: level.tile_width(),
+            tile_height: level.tile_height(),
+            tile_size: ARENA_SCREEN_HEIGHT / level.tile_height() as f32,
+        }
+    }
+
+    pub fn tile_size(&self) -> f32 {
+        self.tile_size
+    }
+
+    /// The area the camera has to show.
+    pub fn view(&self) -> Vec2 {
+        Vec2::new(self.tile_width as f32 * self.tile_size, MAX_Y)
+    }
+
+    /// Transform of the center of a position, used for walls and consumables.
+    pub fn position_transform(&self, position: Position) -> Transform {
+        // the center of the tiles that are covered by the position
+        let offset = (CONSUMABLE_SCALE_FACTOR - 1) as f32 / 2.;
+
+        self.transform(
+            (position.x * CONSUMABLE_SCALE_FACTOR) as f32 + offset,
+            (position.y * CONSUMABLE_SCALE_FACTOR) as f32 + offset,
             1.0,
         )
     }
-}
 
-impl From<Transform> for Position {
-    fn from(transform: Transform) -> Self {
-        Position {
-            x: ((transform.translation.x - UPPER_LEFT.x + MAX_X / 2. - TILE_SIZE / 2.)
-                / (TILE_SIZE * CONSUMABLE_SCALE_FACTOR as f32))
-                .round() as i32,
-            y: ((transform.translation.y - UPPER_LEFT.y + MAX_Y / 2. - TILE_SIZE / 2.)
-                / (TILE_SIZE * CONSUMABLE_SCALE_FACTOR as f32))
-                .round() as i32,
-        }
+    /// Transform of the center of a tile, used for the segments of the diplopod.
+    pub fn tile_transform(&self, tile: Tile) -> Transform {
+        self.transform(tile.x as f32, tile.y as f32, 0.0)
+    }
+
+    /// Converts coordinates measured in tiles to a transform with the center of the arena at the
+    /// origin.
+    fn transform(&self, x: f32, y: f32, z: f32) -> Transform {
+        Transform::from_xyz(
+            (x - (self.tile_width - 1) as f32 / 2.) * self.tile_size,
+            (y - (self.tile_height - 1) as f32 / 2.) * self.tile_size,
+            z,
+        )
     }
 }
 
+/// The simulation of the running game. All entities of the game screen are only a
+/// representation of its state.
+#[derive(Resource, Deref, DerefMut)]
+pub struct CurrentGame(pub Simulation);
+
+/// Seed for the random number generator of every new game. A random seed is chosen for each
+/// game if none is given.
+#[derive(Default, Resource)]
+pub struct Seed(pub Option<u64>);
+
+/// Who plays the following games, as chosen in the menu. The campaign is always played alone.
+#[derive(Default, Resource)]
+pub struct SelectedMode(pub Mode);
+
+/// Measures the time until the next tick. Every game starts with a new one, so that time left
+/// over from the previous game doesn't move the diplopods early.
+#[derive(Default, Resource)]
+struct TickTimer(Timer);
+
+/// Links an entity to a consumable of the simulation.
 #[derive(Component)]
-enum Obstacle {
-    Food,
-    Poison,
-    Superfood,
-    Antidote,
-    Wall,
-}
+pub struct ConsumableId(pub u32);
 
 #[derive(Component)]
 struct OnGameScreen;
 
-#[derive(Default, Resource)]
-pub struct LastSpecialSpawn(pub u32);
-
 #[derive(Message)]
 pub struct GameOver;
 
+/// The goal of the level was reached.
 #[derive(Message)]
-struct SpawnConsumables {
-    pub regular: bool,
-}
+pub struct GoalReached;
 
+/// Lets the gamepads of a player rumble.
 #[derive(Message)]
 enum Rumble {
-    Eat,
-    Death,
+    Eat(usize),
+    Death(usize),
 }
 
 #[derive(Resource)]
@@ -132,6 +161,7 @@ pub struct DiplopodColors {
     pub antidote: MeshMaterial2d<ColorMaterial>,
     pub diplopod_immune: MeshMaterial2d<ColorMaterial>,
     pub diplopod_normal: MeshMaterial2d<ColorMaterial>,
+    pub diplopod_second: MeshMaterial2d<ColorMaterial>,
     pub food: MeshMaterial2d<ColorMaterial>,
     pub poison_fill: MeshMaterial2d<ColorMaterial>,
     pub poison_outline: MeshMaterial2d<ColorMaterial>,
@@ -139,259 +169,338 @@ pub struct DiplopodColors {
     pub wall: MeshMaterial2d<ColorMaterial>,
 }
 
+impl DiplopodColors {
+    /// The color of the diplopod of a player while it isn't immune.
+    pub fn diplopod(&self, player: usize) -> &MeshMaterial2d<ColorMaterial> {
+        if player == 0 {
+            &self.diplopod_normal
+        } else {
+            &self.diplopod_second
+        }
+    }
+}
+
 pub struct GamePlugin;
 
+/// The system that advances the game by a tick, so that other plugins can run before or after it.
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct AdvanceSystems;
+
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::Game), setup_game)
+            // the HUD shows the diplopods of the game that is set up
+            .add_systems(OnEnter(GameState::Game), hud::setup_hud.after(setup_game))
             .add_systems(
                 Update,
                 (
                     (
-                        toggle_pause.run_if(
-                            input_just_pressed(KeyCode::Space)
-                                .or_else(input_just_pressed(KeyCode::KeyP)),
-                        ),
-                        diplopod::keyboard,
-                        diplopod::gamepad,
-                        diplopod::limit_immunity,
+                        diplopod::steer
+                            .run_if(not(resource_exists::<Attract>))
+                            .run_if(in_state(PauseState::Running)),
                         superfood::rotate_superfood,
                         fading_text::fade_text,
+                        hud::update_hud.run_if(resource_exists_and_changed::<CurrentGame>),
                         (
                             diplopod::change_color_during_immunity,
                             antidote::control_antidote_sound,
                         )
-                            .run_if(on_timer(Duration::from_millis(75))),
-                        game_over.run_if(on_message::<GameOver>),
+                            // only advancing the game changes it
+                            .run_if(resource_exists_and_changed::<CurrentGame>),
+                        game_over.run_if(
+                            not(resource_exists::<Attract>).and_then(on_message::<GameOver>),
+                        ),
+                        attract::stop
+                            .run_if(resource_exists::<Attract>.and_then(on_message::<GameOver>)),
+                        goal_reached.run_if(
+                            on_message::<GoalReached>
+                                .and_then(not(resource_exists::<CampaignLevel>))
+                                .and_then(not(resource_exists::<TestPlay>)),
+                        ),
                     )
-                        .run_if(in_state(GameState::Game)),
-                    rumble,
+                        // a game over the network goes on behind the pause menu
+                        .run_if(
+                            in_state(PauseState::Running)
+                                .or_else(resource_exists::<Attract>)
+                                .or_else(resource_exists::<NetworkGame>),
+                        ),
+                    rumble.run_if(not(resource_exists::<Attract>)),
+                    fit_camera.run_if(resource_exists_and_changed::<Arena>),
                 ),
             )
+            // the computer plays in the background of the menu
+            .add_systems(
+                Update,
+                (attract::start, setup_game)
+                    .chain()
+                    .run_if(in_state(GameState::Menu))
+                    .run_if(not(resource_exists::<Attract>)),
+            )
+            .add_systems(OnExit(GameState::Menu), attract::stop)
             .add_systems(
                 FixedUpdate,
                 (
-                    diplopod::movement.run_if(on_timer(Duration::from_millis(75))),
-                    antidote::move_antidote.run_if(on_timer(Duration::from_millis(500))),
-                    check_collision,
-                    spawn_consumables.run_if(on_message::<SpawnConsumables>),
+                    tick_timer,
+                    advance.in_set(AdvanceSystems).run_if(
+                        not(resource_exists::<NetworkGame>)
+                            .and_then(tick_elapsed)
+                            .or_else(network::input_received),
+                    ),
+                    (diplopod::sync_segments, sync_consumables)
+                        .run_if(resource_exists_and_changed::<CurrentGame>),
                 )
-                    .run_if(in_state(GameState::Game)),
+                    .chain()
+                    .run_if(in_state(GameState::Game).or_else(resource_exists::<Attract>)),
             )
             .add_systems(OnExit(GameState::Game), despawn_screen::<OnGameScreen>)
-            .init_resource::<DiplopodSegments>()
-            .init_resource::<LastSpecialSpawn>()
+            .init_resource::<Seed>()
+            .init_resource::<SelectedMode>()
+            .init_resource::<TickTimer>()
             .add_message::<GameOver>()
-            .add_message::<Rumble>()
-            .add_message::<SpawnConsumables>();
+            .add_message::<GoalReached>()
+            .add_message::<Rumble>();
     }
 }
 
-fn setup_game(mut commands: Commands) {
-    commands.queue(SpawnDiplopodSegment);
-
-    for x in 0..CONSUMABLE_WIDTH + 1 {
-        let position = Position { x, y: 0 };
-        commands.queue(SpawnWall { position });
-
-        let position = Position {
-            x,
-            y: CONSUMABLE_HEIGHT,
-        };
-        commands.queue(SpawnWall { position });
-    }
-
-    for y in 1..CONSUMABLE_HEIGHT {
-        let position = Position { x: 0, y };
-        commands.queue(SpawnWall { position });
-
-        let position = Position {
-            x: CONSUMABLE_WIDTH,
-            y,
-        };
+#[allow(clippy::too_many_arguments)]
+fn setup_game(
+    mut commands: Commands,
+    seed: Res<Seed>,
+    settings: Res<Settings>,
+    mode: Res<SelectedMode>,
+    campaign_level: Option<Res<CampaignLevel>>,
+    playback: Option<Res<Playback>>,
+    network: Option<Res<NetworkGame>>,
+    attract: Option<Res<Attract>>,
+    level: Option<Res<LevelHandle>>,
+    levels: Res<Assets<LevelAsset>>,
+) {
+    // the game in the background of the menu is a plain one, whatever was played before
+    let attracting = attract.is_some();
+    let playback = playback.filter(|_| !attracting);
+    let network = network.filter(|_| !attracting);
+    let level = level.filter(|_| !attracting);
+    // a fixed seed from the command line would show the same game over and over
+    let seed = seed.0.filter(|_| !attracting);
+
+    let mode = if campaign_level.is_some() || attracting {
+        Mode::Single
+    } else {
+        mode.0
+    };
+
+    let (seed, rules) = match (playback, network) {
+        (Some(playback), _) => (playback.0.seed(), playback.0.rules()),
+        // the server decides how games over the network are played
+        (None, Some(network)) => (network.seed, network.rules),
+        (None, None) => (
+            seed.unwrap_or_else(rand::random),
+            Rules {
+                movement: settings.movement,
+                reverse_protection: settings.reverse_protection.get(mode),
+                progression: settings.progression,
+                arena: settings.arena,
+                wrap_around: settings.wrap_around,
+                mode,
+            },
+        ),
+    };
+
+    let simulation = match level.and_then(|level| {
+        levels
+            .get(&level.handle)
+            .map(|asset| (level.path.clone(), asset.0.clone()))
+    }) {
+        Some((path, level)) => Simulation::with_level(seed, rules, level, &path),
+        None => Simulation::new(seed, rules),
+    };
+
+    commands.insert_resource(Arena::new(simulation.level()));
+
+    for &position in simulation.walls() {
         commands.queue(SpawnWall { position });
     }
 
-    let mut free_positions = get_randomized_free_positions(vec![START_POSITION.into()]);
-
-    for _ in 0..AMOUNT_OF_FOOD {
-        if let Some(position) = free_positions.pop() {
-            commands.queue(SpawnFood { position });
-        }
+    for player in 0..simulation.diplopods().len() {
+        commands.spawn((Player(player), OnGameScreen));
     }
 
-    for _ in 0..AMOUNT_OF_POISON {
-        if let Some(position) = free_positions.pop() {
-            commands.queue(SpawnPoison { position });
-        }
-    }
+    commands.insert_resource(CurrentGame(simulation));
+    commands.insert_resource(TickTimer::default());
 }
 
-fn get_randomized_free_positions(occupied: Vec<Position>) -> Vec<Position> {
-    let mut free_positions: Vec<Position> =
-        Vec::with_capacity(((CONSUMABLE_WIDTH - 1) * (CONSUMABLE_HEIGHT - 1)) as usize);
-
-    for x in 1..CONSUMABLE_WIDTH {
-        for y in 1..CONSUMABLE_HEIGHT {
-            free_positions.push(Position { x, y });
-        }
-    }
+/// Measures the time of the current tick. The duration of a tick shrinks while the diplopod
+/// grows.
+fn tick_timer(game: Res<CurrentGame>, time: Res<Time>, mut timer: ResMut<TickTimer>) {
+    let tick_duration = game.tick_duration();
 
-    for position in occupied {
-        free_positions.retain(|&p| p != position);
+    if timer.0.duration() != tick_duration {
+        timer.0.set_duration(tick_duration);
+        timer.0.set_mode(TimerMode::Repeating);
     }
 
-    free_positions.shuffle(&mut rng());
+    timer.0.tick(time.delta());
+}
 
-    free_positions
+/// Returns `true` once per tick.
+fn tick_elapsed(timer: Res<TickTimer>) -> bool {
+    timer.0.just_finished()
 }
 
+/// Advances the simulation by one tick with the direction chosen by the player (or taken from
+/// the replay that is played back or sent by the server) and presents everything that happened.
+/// A diplopod of the computer, like the one in the background of the menu, gets its direction
+/// from [`computer::direction`].
 #[allow(clippy::too_many_arguments)]
-fn spawn_consumables(
+fn advance(
     mut commands: Commands,
-    segments: ResMut<DiplopodSegments>,
-    mut spawn_consumables_reader: MessageReader<SpawnConsumables>,
-    obstacles: Query<&Transform>,
-    superfood: Query<Entity, With<Superfood>>,
-    antidotes: Query<Entity, With<Antidote>>,
-    mut last_special_spawn: ResMut<LastSpecialSpawn>,
+    mut game: ResMut<CurrentGame>,
+    arena: Res<Arena>,
+    mut heads: Query<&mut DiplopodHead>,
+    playback: Option<Res<Playback>>,
+    network: Option<Res<NetworkGame>>,
+    settings: Res<Settings>,
+    attract: Option<Res<Attract>>,
+    mut game_over_writer: MessageWriter<GameOver>,
+    mut goal_reached_writer: MessageWriter<GoalReached>,
+    mut rumble_writer: MessageWriter<Rumble>,
     sounds: Res<Sounds>,
 ) {
-    if let Some(spawn_event) = spawn_consumables_reader.read().next() {
-        let mut free_positions =
-            get_randomized_free_positions(obstacles.iter().map(|o| (*o).into()).collect());
+    let input = match (playback, network) {
+        (Some(playback), _) => playback.0.input(game.ticks() + 1),
+        (None, Some(network)) => match network.input(game.ticks() + 1) {
+            Some(input) => input,
+            None => return,
+        },
+        (None, None) => {
+            let mut input = simulation::Input::default();
+
+            for mut head in heads.iter_mut() {
+                input.directions[head.player] = if attract.is_some() {
+                    computer::direction(&game, head.player, attract::DIFFICULTY)
+                } else if is_computer(&game, head.player) {
+                    computer::direction(&game, head.player, settings.difficulty)
+                } else {
+                    let direction = head.next_direction();
+                    Direction::new(direction.x as i32, direction.y as i32)
+                };
+            }
+
+            input
+        }
+    };
+
+    // the game in the background of the menu is played silently
+    let (sound, looped_sound) = if attract.is_some() {
+        (
+            PlaybackSettings::DESPAWN.muted(),
+            PlaybackSettings::LOOP.muted(),
+        )
+    } else {
+        (PlaybackSettings::DESPAWN, PlaybackSettings::LOOP)
+    };
 
-        if spawn_event.regular {
-            if let Some(position) = free_positions.pop() {
-                commands.queue(SpawnFood { position });
+    for event in game.tick(input) {
+        match event {
+            simulation::Event::AteFood { player } => {
+                rumble_writer.write(Rumble::Eat(player));
+
+                commands.spawn((AudioPlayer(sounds.eat_food.clone()), sound));
             }
 
-            if let Some(position) = free_positions.pop() {
-                commands.queue(SpawnPoison { position });
+            simulation::Event::AteSuperfood { player, growth } => {
+                commands.queue(SpawnFadingText {
+                    text: growth.to_string(),
+                    transform: arena.tile_transform(game.diplopods()[player].head()),
+                });
+
+                rumble_writer.write(Rumble::Eat(player));
+
+                commands.spawn((AudioPlayer(sounds.super_food.clone()), sound));
             }
-        }
 
-        if segments.0.len() as u32 - last_special_spawn.0 > SPECIAL_SPAWN_INTERVAL {
-            last_special_spawn.0 =
-                (segments.0.len() as u32 / SPECIAL_SPAWN_INTERVAL) * SPECIAL_SPAWN_INTERVAL;
+            simulation::Event::AtePoison { player } => {
+                rumble_writer.write(Rumble::Eat(player));
 
-            for ent in superfood.iter() {
-                commands.entity(ent).despawn();
+                commands.spawn((AudioPlayer(sounds.eat_poison.clone()), sound));
             }
 
-            if last_special_spawn
-                .0
-                .is_multiple_of(SPECIAL_SPAWN_INTERVAL * 2)
-            {
-                for ent in antidotes.iter() {
-                    commands.entity(ent).despawn();
+            simulation::Event::AteAntidote {
+                player,
+                immunity_started,
+            } => {
+                if immunity_started {
+                    commands.spawn((
+                        AudioPlayer(sounds.antidote.clone()),
+                        looped_sound,
+                        AntidoteSound,
+                        OnGameScreen,
+                    ));
                 }
 
-                if let Some(position) = free_positions.pop() {
-                    commands.queue(SpawnAntidote { position });
-                }
+                rumble_writer.write(Rumble::Eat(player));
+            }
+
+            simulation::Event::SpecialSpawn => {
+                commands.spawn((AudioPlayer(sounds.special_spawn.clone()), sound));
+            }
+
+            simulation::Event::Died { player } => {
+                rumble_writer.write(Rumble::Death(player));
+
+                commands.spawn((AudioPlayer(sounds.game_over.clone()), sound));
             }
 
-            if let Some(position) = free_positions.pop() {
-                commands.queue(SpawnSuperfood { position });
+            simulation::Event::GameOver => {
+                game_over_writer.write(GameOver);
             }
 
-            commands.spawn((
-                AudioPlayer(sounds.special_spawn.clone()),
-                PlaybackSettings::DESPAWN,
-            ));
+            simulation::Event::GoalReached => {
+                commands.spawn((AudioPlayer(sounds.super_food.clone()), sound));
+
+                goal_reached_writer.write(GoalReached);
+            }
         }
     }
 }
 
-fn check_collision(
+/// Returns `true` if the diplopod of the player is steered by the computer.
+pub fn is_computer(game: &Simulation, player: usize) -> bool {
+    game.rules().mode == Mode::Computer && player > 0
+}
+
+/// Spawns, moves and despawns the entities of consumables to match the simulation.
+fn sync_consumables(
     mut commands: Commands,
-    mut heads: Query<(&mut DiplopodHead, &Transform)>,
-    obstacles: Query<(Entity, &Transform, &Obstacle)>,
-    mut spawn_consumables_writer: MessageWriter<SpawnConsumables>,
-    mut game_over_writer: MessageWriter<GameOver>,
-    mut rumble_writer: MessageWriter<Rumble>,
-    sounds: Res<Sounds>,
+    game: Res<CurrentGame>,
+    arena: Res<Arena>,
+    mut consumables: Query<(Entity, &ConsumableId, &mut Transform)>,
 ) {
-    for (mut head, head_transform) in heads.iter_mut() {
-        let head_position: Position = (*head_transform).into();
-        for (entity, transform, obstacle) in obstacles.iter() {
-            if head_position == (*transform).into() {
-                match obstacle {
-                    Obstacle::Food => {
-                        commands.entity(entity).despawn();
-                        commands.queue(SpawnDiplopodSegment);
-
-                        spawn_consumables_writer.write(SpawnConsumables { regular: true });
-                        rumble_writer.write(Rumble::Eat);
-
-                        commands.spawn((
-                            AudioPlayer(sounds.eat_food.clone()),
-                            PlaybackSettings::DESPAWN,
-                        ));
-                    }
-
-                    Obstacle::Superfood => {
-                        commands.entity(entity).despawn();
-                        let growth = rng().random_range(2..10);
-                        for _ in 0..growth {
-                            commands.queue(SpawnDiplopodSegment);
-                        }
-
-                        commands.queue(SpawnFadingText {
-                            text: growth.to_string(),
-                            transform: *head_transform,
-                        });
-
-                        spawn_consumables_writer.write(SpawnConsumables { regular: false });
-                        rumble_writer.write(Rumble::Eat);
-
-                        commands.spawn((
-                            AudioPlayer(sounds.super_food.clone()),
-                            PlaybackSettings::DESPAWN,
-                        ));
-                    }
-
-                    Obstacle::Poison => {
-                        if !head.immunity.is_finished() {
-                            commands.entity(entity).despawn();
-                            commands.queue(SpawnDiplopodSegment);
-
-                            spawn_consumables_writer.write(SpawnConsumables { regular: false });
-                            rumble_writer.write(Rumble::Eat);
-
-                            commands.spawn((
-                                AudioPlayer(sounds.eat_poison.clone()),
-                                PlaybackSettings::DESPAWN,
-                            ));
-                        } else {
-                            game_over_writer.write(GameOver);
-                        }
-                    }
-
-                    Obstacle::Antidote => {
-                        commands.entity(entity).despawn();
-
-                        if head.immunity.is_finished() {
-                            commands.spawn((
-                                AudioPlayer(sounds.antidote.clone()),
-                                PlaybackSettings::LOOP,
-                                AntidoteSound,
-                                OnGameScreen,
-                            ));
-                        }
-
-                        let remaining = head.immunity.remaining_secs();
-                        head.immunity = Timer::from_seconds(10.0 + remaining, TimerMode::Once);
-
-                        rumble_writer.write(Rumble::Eat);
-                    }
-
-                    Obstacle::Wall => {
-                        game_over_writer.write(GameOver);
-                    }
-                };
+    let mut known = Vec::new();
+
+    for (entity, id, mut transform) in consumables.iter_mut() {
+        if let Some(consumable) = game.consumables().iter().find(|c| c.id == id.0) {
+            let target = arena.position_transform(consumable.position);
+            if transform.translation.truncate() != target.translation.truncate() {
+                transform.translation.x = target.translation.x;
+                transform.translation.y = target.translation.y;
             }
+
+            known.push(id.0);
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for consumable in game.consumables().iter().filter(|c| !known.contains(&c.id)) {
+        let position = consumable.position;
+        let id = ConsumableId(consumable.id);
+
+        match consumable.kind {
+            Kind::Food => commands.queue(SpawnFood { position, id }),
+            Kind::Poison => commands.queue(SpawnPoison { position, id }),
+            Kind::Superfood => commands.queue(SpawnSuperfood { position, id }),
+            Kind::Antidote => commands.queue(SpawnAntidote { position, id }),
         }
     }
 }
@@ -400,12 +509,15 @@ fn check_collision(
 fn game_over(
     mut commands: Commands,
     mut reader: MessageReader<GameOver>,
-    mut segments: ResMut<DiplopodSegments>,
-    mut last_special_spawn: ResMut<LastSpecialSpawn>,
+    game: Res<CurrentGame>,
     sounds: Res<Sounds>,
     mut game_state: ResMut<NextState<GameState>>,
     mut lastscore: ResMut<Lastscore>,
-    mut highscore: ResMut<Highscore>,
+    leaderboard: Res<Leaderboard>,
+    mut statistics: ResMut<Statistics>,
+    playback: Option<Res<Playback>>,
+    campaign_level: Option<Res<CampaignLevel>>,
+    test_play: Option<Res<TestPlay>>,
     mut rumble_writer: MessageWriter<Rumble>,
 ) {
     if reader.read().next().is_some() {
@@ -413,54 +525,171 @@ fn game_over(
             AudioPlayer(sounds.game_over.clone()),
             PlaybackSettings::DESPAWN,
         ));
-        rumble_writer.write(Rumble::Death);
 
-        lastscore.0 = segments.0.len() as u16;
+        for (player, diplopod) in game.diplopods().iter().enumerate() {
+            if !diplopod.is_alive() {
+                rumble_writer.write(Rumble::Death(player));
+            }
+        }
 
-        if lastscore.0 > highscore.0 {
-            highscore.0 = lastscore.0;
+        // testing a level in the editor must neither change the leaderboard nor the statistics
+        if test_play.is_some() {
+            game_state.set(GameState::Editor);
+            return;
         }
 
-        last_special_spawn.0 = 0;
+        game_state.set(end_game(
+            &game,
+            playback.is_some(),
+            campaign_level.is_some(),
+            &mut lastscore,
+            &mut statistics,
+            &leaderboard,
+        ));
+    }
+}
+
+/// Ends a game whose goal was reached like one that is over. The campaign continues with the
+/// next level and test play returns to the editor instead, so it only runs without them.
+fn goal_reached(
+    game: Res<CurrentGame>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut lastscore: ResMut<Lastscore>,
+    leaderboard: Res<Leaderboard>,
+    mut statistics: ResMut<Statistics>,
+    playback: Option<Res<Playback>>,
+) {
+    game_state.set(end_game(
+        &game,
+        playback.is_some(),
+        false,
+        &mut lastscore,
+        &mut statistics,
+        &leaderboard,
+    ));
+}
 
-        segments.0 = Vec::new();
+/// Records the score of a finished game and returns the state that shows it.
+fn end_game(
+    game: &Simulation,
+    playback: bool,
+    campaign: bool,
+    lastscore: &mut Lastscore,
+    statistics: &mut Statistics,
+    leaderboard: &Leaderboard,
+) -> GameState {
+    record_game(game, playback, lastscore, statistics);
+
+    let several_players = game.diplopods().len() > 1;
+
+    if playback {
+        return if several_players {
+            GameState::Results
+        } else {
+            GameState::Highscore
+        };
+    }
 
-        game_state.set(GameState::Highscore);
+    // the leaderboard is only for games of a single player and the levels of the campaign
+    // differ too much for a common one
+    if several_players {
+        GameState::Results
+    } else if campaign {
+        GameState::LevelSelect
+    } else if leaderboard.qualifies(lastscore.score, lastscore.rules, lastscore.level.as_deref()) {
+        GameState::NameEntry
+    } else {
+        GameState::Highscore
     }
 }
 
+/// Keeps the length, duration, seed, rules and level of a finished game as last score and adds
+/// the game to the statistics. This is shared by games that are over and levels of the campaign
+/// whose goal was reached.
+pub fn record_game(
+    game: &Simulation,
+    playback: bool,
+    lastscore: &mut Lastscore,
+    statistics: &mut Statistics,
+) {
+    lastscore.score = game.length() as u16;
+    lastscore.duration = game.elapsed();
+    lastscore.seed = game.seed();
+    lastscore.rules = game.rules();
+    lastscore.level = game.replay().level().map(str::to_string);
+
+    // a replay must neither change the leaderboard nor the statistics
+    if playback {
+        return;
+    }
+
+    statistics.games_played += 1;
+    statistics.total_length += u64::from(lastscore.score);
+    statistics.play_time += lastscore.duration;
+}
+
+/// Lets the gamepads rumble that steer the diplopod of the player, like in `diplopod::steer`.
+/// Only the first rumble of every player during a frame counts.
 fn rumble(
     mut rumble_reader: MessageReader<Rumble>,
     mut rumble_writer: MessageWriter<GamepadRumbleRequest>,
-    gamepads: Query<Entity, With<Gamepad>>,
+    connected: Res<ConnectedGamepads>,
+    game: Option<Res<CurrentGame>>,
+    network: Option<Res<NetworkGame>>,
+    settings: Res<Settings>,
 ) {
-    if let Some(rumble) = rumble_reader.read().next() {
-        for gamepad in &gamepads {
+    let (Some(game), true) = (game, settings.rumble) else {
+        rumble_reader.clear();
+        return;
+    };
+
+    let mut rumbled = [false; MAX_PLAYERS];
+
+    for rumble in rumble_reader.read() {
+        let (Rumble::Eat(player) | Rumble::Death(player)) = *rumble;
+
+        if rumbled[player] {
+            continue;
+        }
+
+        rumbled[player] = true;
+
+        let gamepads = match &network {
+            Some(network) if network.player != player => &[][..],
+            Some(_) => connected.all(),
+            None if is_computer(&game, player) => &[][..],
+            None if game.diplopods().len() > 1 && game.rules().mode != Mode::Computer => {
+                connected.players()[player]
+            }
+            None => connected.all(),
+        };
+
+        for &gamepad in gamepads {
             rumble_writer.write(GamepadRumbleRequest::Add {
                 gamepad,
                 intensity: match rumble {
-                    Rumble::Eat => GamepadRumbleIntensity::WEAK_MAX,
-                    Rumble::Death => GamepadRumbleIntensity::MAX,
+                    Rumble::Eat(_) => GamepadRumbleIntensity::WEAK_MAX,
+                    Rumble::Death(_) => GamepadRumbleIntensity::MAX,
                 },
                 duration: match rumble {
-                    Rumble::Eat => Duration::from_millis(100),
-                    Rumble::Death => Duration::from_secs(1),
+                    Rumble::Eat(_) => Duration::from_millis(100),
+                    Rumble::Death(_) => Duration::from_secs(1),
                 },
             });
         }
     }
 }
 
-fn toggle_pause(mut time: ResMut<Time<Virtual>>, sounds: Query<&AudioSink>) {
-    if time.is_paused() {
-        for sound in sounds.iter() {
-            sound.play();
-        }
-        time.unpause();
-    } else {
-        for sound in sounds.iter() {
-            sound.pause();
+/// Shows the whole arena, whatever its aspect ratio.
+fn fit_camera(arena: Res<Arena>, mut projections: Query<&mut Projection, With<Camera2d>>) {
+    let view = arena.view();
+
+    for mut projection in projections.iter_mut() {
+        if let Projection::Orthographic(orthographic) = projection.as_mut() {
+            orthographic.scaling_mode = ScalingMode::AutoMin {
+                min_width: view.x,
+                min_height: view.y,
+            };
         }
-        time.pause();
     }
 }
@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+
+use super::OnGameScreen;
+use crate::simulation::computer::Difficulty;
+
+/// Marks the running game as the one the computer plays in the background of the menu to
+/// attract players. It is played silently, neither counts for the leaderboard nor for the
+/// statistics and simply starts again once it is over.
+#[derive(Resource)]
+pub struct Attract;
+
+/// The computer plays as well as it can to show off the game.
+pub const DIFFICULTY: Difficulty = Difficulty::Hard;
+
+pub(super) fn start(mut commands: Commands) {
+    commands.insert_resource(Attract);
+}
+
+/// Ends the game in the background, which starts a new one while the menu is still shown.
+pub(super) fn stop(mut commands: Commands, entities: Query<Entity, With<OnGameScreen>>) {
+    commands.remove_resource::<Attract>();
+
+    for entity in &entities {
+        commands.entity(entity).despawn();
+    }
+}
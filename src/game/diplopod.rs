@@ -2,223 +2,295 @@ use bevy::{
     ecs::{lifecycle::HookContext, system::SystemState, world::DeferredWorld},
     prelude::*,
 };
+use std::collections::VecDeque;
 
 use crate::{
-    MAX_X, MAX_Y,
-    game::{CommandResources, DiplopodColors},
+    controls::{Action, PlayerActions},
+    game::{Arena, CommandResources, CurrentGame, DiplopodColors, is_computer},
+    network::NetworkGame,
+    simulation::{Direction, MAX_PLAYERS, Mode, Movement, Tile},
 };
 
-use super::{ARENA_HEIGHT, ARENA_WIDTH, GameOver, OnGameScreen, TILE_SIZE, UPPER_LEFT};
+use super::OnGameScreen;
 
-pub const START_POSITION: Transform = Transform::from_xyz(
-    (ARENA_WIDTH / 2) as f32 * TILE_SIZE + UPPER_LEFT.x - MAX_X / 2.,
-    (ARENA_HEIGHT / 2) as f32 * TILE_SIZE + UPPER_LEFT.y - MAX_Y / 2.,
-    0.0,
-);
+/// The diplopod of a player. Its segments are entities of their own, which are listed in
+/// [`DiplopodSegments`].
+#[derive(Component)]
+#[require(DiplopodSegments)]
+pub struct Player(pub usize);
 
-#[derive(Default, Resource)]
+/// The segment entities of a diplopod, starting with the head.
+#[derive(Default, Component)]
 pub struct DiplopodSegments(pub Vec<Entity>);
 
+/// Maximum number of turns that are buffered until the next ticks.
+const MAX_PENDING_TURNS: usize = 3;
+
+/// Marks the head of the diplopod and holds the direction chosen by the player. Turns that are
+/// made faster than the diplopod moves are buffered and taken one per tick, so that none of them
+/// gets lost.
 #[derive(Component)]
 pub struct DiplopodHead {
-    pub direction: Vec2,
-    pub immunity: Timer,
+    pub player: usize,
+    direction: Vec2,
+    pending_turns: VecDeque<Vec2>,
 }
 
-impl Default for DiplopodHead {
-    fn default() -> Self {
+impl DiplopodHead {
+    /// A head that moves into the given direction before the player steers it.
+    fn moving(player: usize, direction: Direction) -> Self {
         Self {
-            direction: Vec2::ZERO,
-            immunity: Timer::from_seconds(0.0, TimerMode::Once),
+            player,
+            direction: Vec2::new(direction.x as f32, direction.y as f32),
+            pending_turns: VecDeque::new(),
+        }
+    }
+
+    /// Direction the diplopod will move into once all buffered turns are taken.
+    fn last_direction(&self) -> Vec2 {
+        self.pending_turns.back().copied().unwrap_or(self.direction)
+    }
+
+    /// Buffers a turn into the given direction, unless it is the direction the diplopod will move
+    /// into anyway or too many turns are buffered already.
+    fn turn(&mut self, direction: Vec2) {
+        if direction != self.last_direction() && self.pending_turns.len() < MAX_PENDING_TURNS {
+            self.pending_turns.push_back(direction);
+        }
+    }
+
+    /// Takes the next buffered turn and returns the direction for the next tick.
+    pub fn next_direction(&mut self) -> Vec2 {
+        if let Some(direction) = self.pending_turns.pop_front() {
+            self.direction = direction;
         }
+
+        self.direction
     }
 }
 
+/// A segment of the diplopod of the [`Player`] entity it refers to.
 #[derive(Component)]
 #[component(on_add=on_add_diplopod_segment)]
-pub struct DiplopodSegment;
+pub struct DiplopodSegment {
+    pub diplopod: Entity,
+}
 
 fn on_add_diplopod_segment(mut world: DeferredWorld, HookContext { entity, .. }: HookContext) {
-    world.resource_mut::<DiplopodSegments>().0.push(entity);
+    let diplopod = world.get::<DiplopodSegment>(entity).unwrap().diplopod;
+
+    if let Some(mut segments) = world.get_mut::<DiplopodSegments>(diplopod) {
+        segments.0.push(entity);
+    }
 }
 
-pub struct SpawnDiplopodSegment;
+pub struct SpawnDiplopodSegment {
+    pub diplopod: Entity,
+    pub tile: Tile,
+}
 
 impl Command for SpawnDiplopodSegment {
     type Out = ();
 
     fn apply(self, world: &mut World) {
-        let segments = &world.resource::<DiplopodSegments>().0;
-        let is_head = segments.is_empty();
-
-        let position = if is_head {
-            START_POSITION
-        } else {
-            *world.get::<Transform>(*segments.last().unwrap()).unwrap()
+        // the diplopod is gone if the game was left in the meantime
+        let Some(player) = world.get::<Player>(self.diplopod).map(|player| player.0) else {
+            return;
         };
 
-        let immune = if is_head {
-            false
-        } else {
-            !world
-                .get::<DiplopodHead>(*segments.first().unwrap())
-                .unwrap()
-                .immunity
-                .is_finished()
-        };
+        let is_head = world
+            .get::<DiplopodSegments>(self.diplopod)
+            .is_none_or(|segments| segments.0.is_empty());
+        let diplopod = &world.resource::<CurrentGame>().diplopods()[player];
+        let immune = diplopod.is_immune();
+        // the head is spawned before the diplopod moves for the first time
+        let start_direction = diplopod.direction();
 
         let mut command_resources: CommandResources = SystemState::new(world);
-        let (mut commands, mut meshes, colors) = command_resources.get_mut(world).unwrap();
+        let (mut commands, mut meshes, colors, arena) = command_resources.get_mut(world).unwrap();
 
         let color = if immune {
             colors.diplopod_immune.clone()
         } else {
-            colors.diplopod_normal.clone()
+            colors.diplopod(player).clone()
         };
 
+        let transform = arena.tile_transform(self.tile);
+
         let mut segment = commands.spawn((
-            Mesh2d(meshes.add(Rectangle::new(TILE_SIZE, TILE_SIZE))),
+            Mesh2d(meshes.add(Rectangle::new(arena.tile_size(), arena.tile_size()))),
             color,
-            position,
-            DiplopodSegment,
+            transform,
+            DiplopodSegment {
+                diplopod: self.diplopod,
+            },
             OnGameScreen,
         ));
 
         if is_head {
-            segment.insert(DiplopodHead::default());
+            segment.insert(DiplopodHead::moving(player, start_direction));
         }
 
         command_resources.apply(world);
     }
 }
 
-pub fn keyboard(keyboard_input: Res<ButtonInput<KeyCode>>, mut heads: Query<&mut DiplopodHead>) {
-    if let Some(mut head) = heads.iter_mut().next() {
-        let mut direction = Vec2::ZERO;
-
-        if keyboard_input.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA, KeyCode::KeyH]) {
-            direction = Vec2::new(-1.0, 0.0);
-        }
-
-        if keyboard_input.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD, KeyCode::KeyL]) {
-            direction = Vec2::new(1.0, 0.0);
-        }
-
-        if keyboard_input.any_pressed([KeyCode::ArrowUp, KeyCode::KeyW, KeyCode::KeyK]) {
-            direction = Vec2::new(direction.x, 1.0);
-        }
-
-        if keyboard_input.any_pressed([KeyCode::ArrowDown, KeyCode::KeyS, KeyCode::KeyJ]) {
-            direction = Vec2::new(direction.x, -1.0);
-        }
+/// Changes the direction of the diplopods according to the pressed actions and the movement of
+/// the game. If several directions are pressed at the same time, the one that was pressed last
+/// wins. With several players each diplopod follows the devices of its player, unless the other
+/// players play over the network. The diplopod of the computer is steered in `advance`, while
+/// the player uses all devices.
+pub fn steer(
+    actions: Res<ButtonInput<Action>>,
+    player_actions: Res<PlayerActions>,
+    game: Res<CurrentGame>,
+    network: Option<Res<NetworkGame>>,
+    mut heads: Query<&mut DiplopodHead>,
+    mut pressed: Local<[Vec<Action>; MAX_PLAYERS]>,
+) {
+    let several_players = game.diplopods().len() > 1;
+
+    for mut head in heads.iter_mut() {
+        let player = head.player;
+        let actions = match &network {
+            Some(network) if network.player != player => continue,
+            Some(_) => &*actions,
+            None if is_computer(&game, player) => continue,
+            None if several_players && game.rules().mode != Mode::Computer => {
+                &player_actions.0[player]
+            }
+            None => &*actions,
+        };
 
-        if direction != Vec2::ZERO {
-            head.direction = direction;
-        }
+        steer_head(actions, &game, &mut head, &mut pressed[player]);
     }
 }
 
-pub fn gamepad(gamepads: Query<&Gamepad>, mut heads: Query<&mut DiplopodHead>) {
-    const TILT: f32 = 0.9;
-
-    if let Some(mut head) = heads.iter_mut().next() {
-        let mut direction = Vec2::ZERO;
-
-        for gamepad in gamepads.iter() {
-            if let Some(left_stick_x) = gamepad.get(GamepadAxis::LeftStickX) {
-                if left_stick_x <= -TILT {
-                    direction = Vec2::new(-1.0, 0.0);
-                }
-
-                if left_stick_x >= TILT {
-                    direction = Vec2::new(1.0, 0.0);
-                }
-            }
-
-            if let Some(left_stick_y) = gamepad.get(GamepadAxis::LeftStickY) {
-                if left_stick_y <= -TILT {
-                    direction = Vec2::new(direction.x, -1.0);
-                }
+fn steer_head(
+    actions: &ButtonInput<Action>,
+    game: &CurrentGame,
+    head: &mut DiplopodHead,
+    pressed: &mut Vec<Action>,
+) {
+    pressed.retain(|&action| actions.pressed(action));
 
-                if left_stick_y >= TILT {
-                    direction = Vec2::new(direction.x, 1.0);
-                }
-            }
-        }
+    for &action in actions.get_just_pressed() {
+        pressed.push(action);
+    }
 
-        if direction != Vec2::ZERO {
-            head.direction = direction;
+    // actions that were already pressed before the game started count as pressed first
+    for &action in actions.get_pressed() {
+        if !pressed.contains(&action) {
+            pressed.insert(0, action);
         }
     }
-}
 
-pub fn movement(
-    mut heads: Query<(Entity, &DiplopodHead)>,
-    mut positions: Query<&mut Transform>,
-    segments: ResMut<DiplopodSegments>,
-    mut game_over_writer: MessageWriter<GameOver>,
-) {
-    if let Some((head_entity, head)) = heads.iter_mut().next() {
-        let segment_positions = segments
-            .0
+    let last_pressed = |candidates: &[Action]| {
+        pressed
             .iter()
-            .map(|e| positions.get_mut(*e).unwrap().translation)
-            .collect::<Vec<Vec3>>();
+            .rev()
+            .find(|action| candidates.contains(action))
+            .map_or(Vec2::ZERO, |&action| action_direction(action))
+    };
+
+    let last = head.last_direction();
+    let rules = game.rules();
+    let movement = rules.movement;
+
+    let direction = match movement {
+        Movement::EightWay => {
+            last_pressed(&[Action::Left, Action::Right]) + last_pressed(&[Action::Up, Action::Down])
+        }
+        Movement::FourWay => last_pressed(&[Action::Left, Action::Right, Action::Up, Action::Down]),
+        Movement::Turns if last == Vec2::ZERO => {
+            last_pressed(&[Action::Left, Action::Right, Action::Up, Action::Down])
+        }
+        Movement::Turns => {
+            if actions.just_pressed(Action::Left) {
+                last.perp()
+            } else if actions.just_pressed(Action::Right) {
+                -last.perp()
+            } else {
+                Vec2::ZERO
+            }
+        }
+    };
 
-        let mut head_pos = positions.get_mut(head_entity).unwrap();
-        head_pos.translation.x += head.direction.x * TILE_SIZE;
-        head_pos.translation.y += head.direction.y * TILE_SIZE;
+    // reversing is ignored by the simulation, so it must not take up a buffered turn
+    let may_reverse = movement == Movement::EightWay
+        && !(rules.reverse_protection && game.diplopods()[head.player].segments().len() > 1);
 
-        if segment_positions.contains(&head_pos.translation)
-            && (head.direction.x != 0.0 || head.direction.y != 0.0)
-        {
-            game_over_writer.write(GameOver);
-        }
+    if direction != Vec2::ZERO && (may_reverse || direction != -last) {
+        head.turn(direction);
+    }
+}
 
-        segment_positions
-            .iter()
-            .zip(segments.0.iter().skip(1))
-            .for_each(|(pos, segment)| {
-                positions.get_mut(*segment).unwrap().translation = *pos;
-            });
+/// Direction of the actions up, down, left and right.
+fn action_direction(action: Action) -> Vec2 {
+    match action {
+        Action::Left => Vec2::new(-1.0, 0.0),
+        Action::Right => Vec2::new(1.0, 0.0),
+        Action::Up => Vec2::new(0.0, 1.0),
+        Action::Down => Vec2::new(0.0, -1.0),
+        _ => Vec2::ZERO,
     }
 }
 
-pub fn limit_immunity(mut heads: Query<&mut DiplopodHead>, time: Res<Time>) {
-    if let Ok(mut head) = heads.single_mut()
-        && !head.immunity.is_finished()
-    {
-        head.immunity.tick(time.delta());
+/// Moves the segment entities to the tiles of the simulated diplopod and spawns missing segments.
+pub fn sync_segments(
+    mut commands: Commands,
+    game: Res<CurrentGame>,
+    arena: Res<Arena>,
+    diplopods: Query<(Entity, &Player, &DiplopodSegments)>,
+    mut transforms: Query<&mut Transform, With<DiplopodSegment>>,
+) {
+    for (entity, player, segments) in diplopods.iter() {
+        for (index, &tile) in game.diplopods()[player.0].segments().iter().enumerate() {
+            if let Some(&segment) = segments.0.get(index) {
+                if let Ok(mut transform) = transforms.get_mut(segment) {
+                    *transform = arena.tile_transform(tile);
+                }
+            } else {
+                commands.queue(SpawnDiplopodSegment {
+                    diplopod: entity,
+                    tile,
+                });
+            }
+        }
     }
 }
 
+/// Shows the immunity of each diplopod. The color flashes while the immunity wears off.
 pub fn change_color_during_immunity(
     mut query: Query<&mut MeshMaterial2d<ColorMaterial>, With<DiplopodSegment>>,
-    heads: Query<&DiplopodHead>,
+    game: Res<CurrentGame>,
+    diplopods: Query<(&Player, &DiplopodSegments)>,
     diplopod_colors: Res<DiplopodColors>,
 ) {
-    if let Ok(head) = heads.single() {
-        let target_color = if head.immunity.remaining_secs() > 2.0 {
+    for (&Player(player), segments) in diplopods.iter() {
+        let diplopod = &game.diplopods()[player];
+        let normal = diplopod_colors.diplopod(player);
+        let current_color = segments
+            .0
+            .first()
+            .and_then(|&head| query.get(head).ok())
+            .map(|material| material.0.clone());
+
+        // the color flashes while the immunity wears off
+        let immune = game.immunity_remaining(player).as_secs_f32() > 2.0
+            || (diplopod.is_immune() && current_color.as_ref() == Some(&normal.0));
+
+        let target_color = if immune {
             diplopod_colors.diplopod_immune.clone()
-        } else if !head.immunity.is_finished() {
-            if let Some(current_color) = query.iter().next() {
-                if current_color.0 == diplopod_colors.diplopod_normal.0 {
-                    diplopod_colors.diplopod_immune.clone()
-                } else {
-                    diplopod_colors.diplopod_normal.clone()
-                }
-            } else {
-                diplopod_colors.diplopod_normal.clone()
-            }
         } else {
-            diplopod_colors.diplopod_normal.clone()
+            normal.clone()
         };
 
-        if let Some(current_color) = query.iter().next()
-            && current_color.0 != target_color.0
-        {
-            for mut segment_color in query.iter_mut() {
-                *segment_color = target_color.clone();
+        if current_color.is_some_and(|current_color| current_color != target_color.0) {
+            for &segment in &segments.0 {
+                if let Ok(mut segment_color) = query.get_mut(segment) {
+                    *segment_color = target_color.clone();
+                }
             }
         }
     }
@@ -2,10 +2,11 @@ use bevy::{ecs::system::SystemState, prelude::*};
 
 use crate::game::CommandResources;
 
-use super::{Obstacle, OnGameScreen, Position, RADIUS_FACTOR, TILE_SIZE};
+use super::{ConsumableId, OnGameScreen, Position, RADIUS_FACTOR};
 
 pub struct SpawnFood {
     pub position: Position,
+    pub id: ConsumableId,
 }
 
 impl Command for SpawnFood {
@@ -13,15 +14,15 @@ impl Command for SpawnFood {
 
     fn apply(self, world: &mut World) {
         let mut command_resources: CommandResources = SystemState::new(world);
-        let (mut commands, mut meshes, colors) = command_resources.get_mut(world).unwrap();
+        let (mut commands, mut meshes, colors, arena) = command_resources.get_mut(world).unwrap();
 
-        let transform: Transform = self.position.into();
+        let transform = arena.position_transform(self.position);
 
         commands.spawn((
-            Mesh2d(meshes.add(Circle::new(TILE_SIZE * RADIUS_FACTOR))),
+            Mesh2d(meshes.add(Circle::new(arena.tile_size() * RADIUS_FACTOR))),
             colors.food.clone(),
             transform,
-            Obstacle::Food,
+            self.id,
             OnGameScreen,
         ));
 
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+
+use crate::highscore::{Leaderboard, format_duration};
+use crate::settings::Settings;
+use crate::simulation::level::Goal;
+use crate::simulation::{IMMUNITY_TICKS, Kind, Mode, TICK_DURATION};
+
+use super::{CurrentGame, OnGameScreen};
+
+const HUD_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const HUD_BACKGROUND_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const IMMUNITY_BAR_COLOR: Color = Color::WHITE;
+const IMMUNITY_BAR_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const IMMUNITY_BAR_WIDTH: f32 = 200.0;
+
+/// A value of the running game that is displayed on the HUD.
+#[derive(Clone, Copy, Component)]
+pub enum HudText {
+    Length,
+    Highscore,
+    Poison,
+    Time,
+    /// The current speed relative to the speed at the start of a classic game.
+    Speed,
+    /// How far the goal of the level has been reached. It stays empty for levels without a goal.
+    Goal,
+}
+
+/// The part of the immunity bar of a player that shrinks while the immunity wears off.
+#[derive(Component)]
+pub struct ImmunityBar(usize);
+
+/// Creates the overlay that shows the state of the running game. Every diplopod has its own
+/// immunity bar, which has its color if there are several.
+pub fn setup_hud(
+    mut commands: Commands,
+    game: Res<CurrentGame>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+    let players = game.diplopods().len();
+    let palette = settings.theme.palette();
+    let colors = if players > 1 {
+        [palette.diplopod, palette.diplopod_second]
+    } else {
+        [IMMUNITY_BAR_COLOR; 2]
+    };
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.0),
+                width: Val::Percent(100.0),
+                padding: UiRect::axes(Val::Px(20.0), Val::Px(5.0)),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(30.0),
+                ..default()
+            },
+            BackgroundColor(HUD_BACKGROUND_COLOR),
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            for hud_text in [HudText::Length, HudText::Highscore, HudText::Poison] {
+                spawn_text(parent, &font, hud_text);
+            }
+
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for player in 0..players {
+                        parent
+                            .spawn((
+                                Node {
+                                    width: Val::Px(IMMUNITY_BAR_WIDTH),
+                                    height: Val::Px(12.0 / players as f32),
+                                    ..default()
+                                },
+                                BackgroundColor(IMMUNITY_BAR_BACKGROUND_COLOR),
+                            ))
+                            .with_child((
+                                Node {
+                                    width: Val::Percent(0.0),
+                                    height: Val::Percent(100.0),
+                                    ..default()
+                                },
+                                BackgroundColor(colors[player % colors.len()]),
+                                ImmunityBar(player),
+                            ));
+                    }
+                });
+
+            for hud_text in [HudText::Time, HudText::Speed, HudText::Goal] {
+                spawn_text(parent, &font, hud_text);
+            }
+        });
+}
+
+fn spawn_text(parent: &mut ChildSpawnerCommands, font: &FontSource, hud_text: HudText) {
+    parent.spawn((
+        Text::default(),
+        TextFont {
+            font: font.clone(),
+            font_size: FontSize::Px(24.0),
+            ..default()
+        },
+        TextColor(HUD_COLOR),
+        hud_text,
+    ));
+}
+
+pub fn update_hud(
+    game: Res<CurrentGame>,
+    leaderboard: Res<Leaderboard>,
+    mut texts: Query<(&HudText, &mut Text)>,
+    mut immunity_bars: Query<(&ImmunityBar, &mut Node)>,
+) {
+    let length = game.diplopod().segments().len();
+    let mode = game.rules().mode;
+    let several_players = game.diplopods().len() > 1;
+
+    for (hud_text, mut text) in texts.iter_mut() {
+        text.0 = match hud_text {
+            HudText::Length if mode == Mode::Coop => format!("Length {}", game.length()),
+            HudText::Length => {
+                let lengths: Vec<String> = game
+                    .diplopods()
+                    .iter()
+                    .map(|diplopod| diplopod.segments().len().to_string())
+                    .collect();
+
+                format!("Length {}", lengths.join(" : "))
+            }
+            // the leaderboard only has games of a single player
+            HudText::Highscore if several_players => String::new(),
+            HudText::Highscore => {
+                let best = leaderboard.best(game.rules(), game.replay().level());
+                format!("Highscore {}", best.max(length as u16))
+            }
+            HudText::Poison => format!(
+                "Poison {}",
+                game.consumables()
+                    .iter()
+                    .filter(|consumable| consumable.kind == Kind::Poison)
+                    .count()
+            ),
+            HudText::Time => format!("Time {}", format_duration(game.elapsed())),
+            HudText::Speed => format!(
+                "Speed {:.2}x",
+                TICK_DURATION.as_secs_f32() / game.tick_duration().as_secs_f32()
+            ),
+            // goals do not count when the players compete
+            HudText::Goal if mode.is_competitive() => String::new(),
+            HudText::Goal => match game.level().goal {
+                None => String::new(),
+                Some(Goal::Length(goal)) => format!("Goal {}/{}", game.length(), goal),
+                Some(Goal::ClearPoison) => "Goal no poison".to_string(),
+                Some(Goal::Survive(duration)) => format!(
+                    "Goal {}",
+                    format_duration(duration.saturating_sub(game.elapsed()))
+                ),
+            },
+        };
+    }
+
+    for (ImmunityBar(player), mut node) in immunity_bars.iter_mut() {
+        let Some(diplopod) = game.diplopods().get(*player) else {
+            continue;
+        };
+
+        // several antidotes may add up to more immunity than the bar shows
+        let immunity = (diplopod.immunity() as f32 / IMMUNITY_TICKS as f32).min(1.0);
+        node.width = Val::Percent(immunity * 100.0);
+    }
+}
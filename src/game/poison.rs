@@ -2,12 +2,13 @@ use bevy::{ecs::system::SystemState, prelude::*};
 
 use crate::game::CommandResources;
 
-use super::{Obstacle, OnGameScreen, Position, RADIUS_FACTOR, TILE_SIZE};
+use super::{ConsumableId, OnGameScreen, Position, RADIUS_FACTOR};
 
 const FILL_RADIUS_FACTOR: f32 = 0.7;
 
 pub struct SpawnPoison {
     pub position: Position,
+    pub id: ConsumableId,
 }
 
 impl Command for SpawnPoison {
@@ -15,20 +16,22 @@ impl Command for SpawnPoison {
 
     fn apply(self, world: &mut World) {
         let mut command_resources: CommandResources = SystemState::new(world);
-        let (mut commands, mut meshes, colors) = command_resources.get_mut(world).unwrap();
+        let (mut commands, mut meshes, colors, arena) = command_resources.get_mut(world).unwrap();
 
-        let transform: Transform = self.position.into();
+        let transform = arena.position_transform(self.position);
 
         commands
             .spawn((
-                Mesh2d(meshes.add(Circle::new(TILE_SIZE * RADIUS_FACTOR))),
+                Mesh2d(meshes.add(Circle::new(arena.tile_size() * RADIUS_FACTOR))),
                 colors.poison_outline.clone(),
                 transform,
-                Obstacle::Poison,
+                self.id,
                 OnGameScreen,
             ))
             .with_child((
-                Mesh2d(meshes.add(Circle::new(TILE_SIZE * RADIUS_FACTOR * FILL_RADIUS_FACTOR))),
+                Mesh2d(meshes.add(Circle::new(
+                    arena.tile_size() * RADIUS_FACTOR * FILL_RADIUS_FACTOR,
+                ))),
                 colors.poison_fill.clone(),
                 Transform::from_translation(Vec3::new(0.0, 0.0, 1.0)),
             ));
@@ -2,7 +2,7 @@ use bevy::{ecs::system::SystemState, prelude::*};
 
 use crate::game::CommandResources;
 
-use super::{Obstacle, OnGameScreen, Position, TILE_SIZE};
+use super::{ConsumableId, OnGameScreen, Position};
 
 const STROKE_WIDTH: f32 = 7.5;
 
@@ -11,6 +11,7 @@ pub struct Superfood;
 
 pub struct SpawnSuperfood {
     pub position: Position,
+    pub id: ConsumableId,
 }
 
 impl Command for SpawnSuperfood {
@@ -18,20 +19,20 @@ impl Command for SpawnSuperfood {
 
     fn apply(self, world: &mut World) {
         let mut command_resources: CommandResources = SystemState::new(world);
-        let (mut commands, mut meshes, colors) = command_resources.get_mut(world).unwrap();
+        let (mut commands, mut meshes, colors, arena) = command_resources.get_mut(world).unwrap();
 
-        let transform: Transform = self.position.into();
+        let transform = arena.position_transform(self.position);
         commands
             .spawn((
-                Mesh2d(meshes.add(Rectangle::new(TILE_SIZE * 2.0, STROKE_WIDTH))),
+                Mesh2d(meshes.add(Rectangle::new(arena.tile_size() * 2.0, STROKE_WIDTH))),
                 colors.superfood.clone(),
                 transform,
-                Obstacle::Superfood,
+                self.id,
                 Superfood,
                 OnGameScreen,
             ))
             .with_child((
-                Mesh2d(meshes.add(Rectangle::new(STROKE_WIDTH, TILE_SIZE * 2.0))),
+                Mesh2d(meshes.add(Rectangle::new(STROKE_WIDTH, arena.tile_size() * 2.0))),
                 colors.superfood.clone(),
             ));
 
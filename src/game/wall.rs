@@ -2,7 +2,7 @@ use bevy::{ecs::system::SystemState, prelude::*};
 
 use crate::game::CommandResources;
 
-use super::{Obstacle, OnGameScreen, Position, TILE_SIZE};
+use super::{OnGameScreen, Position};
 
 pub struct SpawnWall {
     pub position: Position,
@@ -13,15 +13,17 @@ impl Command for SpawnWall {
 
     fn apply(self, world: &mut World) {
         let mut command_resources: CommandResources = SystemState::new(world);
-        let (mut commands, mut meshes, colors) = command_resources.get_mut(world).unwrap();
+        let (mut commands, mut meshes, colors, arena) = command_resources.get_mut(world).unwrap();
 
-        let transform: Transform = self.position.into();
+        let transform = arena.position_transform(self.position);
 
         commands.spawn((
-            Mesh2d(meshes.add(Rectangle::new(TILE_SIZE * 2.0, TILE_SIZE * 2.0))),
+            Mesh2d(meshes.add(Rectangle::new(
+                arena.tile_size() * 2.0,
+                arena.tile_size() * 2.0,
+            ))),
             colors.wall.clone(),
             transform,
-            Obstacle::Wall,
             OnGameScreen,
         ));
 
@@ -1,38 +1,129 @@
-use anyhow::Result;
-use anyhow::anyhow;
 use bevy::color::palettes::css::ANTIQUE_WHITE;
+use bevy::prelude::*;
 use bevy::time::common_conditions::on_timer;
-use bevy::{input::ButtonState, input::keyboard::KeyboardInput, prelude::*};
-use directories::ProjectDirs;
-use std::fs::File;
-use std::io::Write;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-use crate::TITLE;
+use crate::controls::Action;
+use crate::save::Statistics;
+use crate::simulation::Rules;
 
 use super::{GameState, despawn_screen};
 
-/// Adds a screen that shows the highscore of the current session and
-/// the score of the last game.
+/// Adds a screen that shows the leaderboard and the score and seed of the last game.
 pub struct HighscorePlugin;
 
-const TITLE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
-const HEADLINE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TABLE_HEADER_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
 const HIGHSCORE_COLOR: Color = Color::WHITE;
+const LAST_ENTRY_COLOR: Color = Color::srgb(1.0, 0.65, 0.0);
 const INITIAL_DELAY_MILLISECONDS: u64 = 500;
 
-const QUALIFIER: &str = "com.github";
-const ORGANIZATION: &str = "tehlers";
-const HIGHSCORE: &str = "highscore";
+/// Maximum number of entries in the leaderboard.
+pub const LEADERBOARD_SIZE: usize = 10;
+
+/// Width of the columns rank, name, length, duration, date and seed.
+const COLUMN_WIDTHS: [f32; 6] = [60.0, 100.0, 110.0, 110.0, 180.0, 260.0];
 
 #[derive(Component)]
 struct OnHighscoreScreen;
 
+/// A single run in the leaderboard. The seed, rules and level together place food, poison and
+/// specials in the same way again, the run itself is only kept in `highscore.replay`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// Seconds since the Unix epoch, `0` if unknown.
+    pub date: u64,
+    pub length: u16,
+    pub duration: Duration,
+    pub seed: u64,
+    #[serde(default)]
+    pub rules: Rules,
+    /// Path of the level, if the run was not played in a bordered arena.
+    #[serde(default)]
+    pub level: Option<String>,
+}
+
+/// The best runs, ordered from best to worst. Runs only compete with the runs of the same rules
+/// and level, each of which keeps its own top ten.
 #[derive(Default, Resource)]
-pub struct Highscore(pub u16);
+pub struct Leaderboard {
+    pub entries: Vec<Entry>,
+    /// Index of the entry added last during this session.
+    pub last_entry: Option<usize>,
+}
+
+impl Entry {
+    /// Returns `true` if the run was played with the given rules and level.
+    pub fn played_with(&self, rules: Rules, level: Option<&str>) -> bool {
+        self.rules == rules && self.level.as_deref() == level
+    }
+}
+
+impl Leaderboard {
+    /// The runs with the given rules and level together with their index, ordered from best to
+    /// worst.
+    pub fn board<'a>(
+        &'a self,
+        rules: Rules,
+        level: Option<&'a str>,
+    ) -> impl Iterator<Item = (usize, &'a Entry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| entry.played_with(rules, level))
+    }
+
+    /// Length of the best run with the given rules and level.
+    pub fn best(&self, rules: Rules, level: Option<&str>) -> u16 {
+        self.board(rules, level)
+            .next()
+            .map_or(0, |(_, entry)| entry.length)
+    }
+
+    /// Returns `true` if a run of the given length, rules and level would be added to the
+    /// leaderboard.
+    pub fn qualifies(&self, length: u16, rules: Rules, level: Option<&str>) -> bool {
+        length > 0
+            && self
+                .board(rules, level)
+                .nth(LEADERBOARD_SIZE - 1)
+                .is_none_or(|(_, entry)| length > entry.length)
+    }
+
+    /// Adds a run behind all runs of the same or a greater length and drops the worst run with
+    /// the same rules and level if there are too many of them.
+    pub fn insert(&mut self, entry: Entry) {
+        let index = self
+            .entries
+            .partition_point(|existing| existing.length >= entry.length);
+        let (rules, level) = (entry.rules, entry.level.clone());
+
+        self.entries.insert(index, entry);
+
+        let dropped = self
+            .board(rules, level.as_deref())
+            .nth(LEADERBOARD_SIZE)
+            .map(|(dropped, _)| dropped);
+
+        if let Some(dropped) = dropped {
+            self.entries.remove(dropped);
+        }
+
+        self.last_entry = (dropped != Some(index)).then_some(index);
+    }
+}
 
 #[derive(Default, Resource)]
-pub struct Lastscore(pub u16);
+pub struct Lastscore {
+    pub score: u16,
+    pub duration: Duration,
+    /// Seed, rules and level of the last game, which are kept with its entry in the leaderboard.
+    pub seed: u64,
+    pub rules: Rules,
+    pub level: Option<String>,
+}
 
 #[derive(Default, Resource)]
 pub struct InitialDelay;
@@ -43,7 +134,7 @@ impl Plugin for HighscorePlugin {
             .add_systems(
                 Update,
                 (
-                    (gamepad, keyboard)
+                    navigate
                         .run_if(in_state(GameState::Highscore))
                         .run_if(not(resource_exists::<InitialDelay>)),
                     remove_initial_delay
@@ -55,66 +146,56 @@ impl Plugin for HighscorePlugin {
                 OnExit(GameState::Highscore),
                 despawn_screen::<OnHighscoreScreen>,
             )
-            .add_systems(
-                Update,
-                save_highscore.run_if(
-                    resource_changed::<Highscore>.and_then(not(resource_added::<Highscore>)),
-                ),
-            )
-            .insert_resource(load_highscore())
             .init_resource::<Lastscore>();
     }
 }
 
-/// Load highscore from platform specific data directory (e.g.
-/// `$HOME/.local/share/diplopod/highscore`). If the file is invalid or inaccessible the highscore will
-/// be set back to zero.
-fn load_highscore() -> Highscore {
-    match read_highscore_from_file() {
-        Ok(highscore) => Highscore(highscore),
-        Err(e) => {
-            warn!("{}", e);
-            Highscore::default()
-        }
+/// Seconds since the Unix epoch. The system time is not available on the web, where `0` is
+/// returned instead.
+pub fn now() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
     }
-}
 
-/// Reads the highscore from the platform specific data directory and tries to parse the value as
-/// `u16`.
-fn read_highscore_from_file() -> Result<u16> {
-    if let Some(projects_dirs) = ProjectDirs::from(QUALIFIER, ORGANIZATION, TITLE) {
-        Ok(std::fs::read_to_string(projects_dirs.data_dir().join(HIGHSCORE))?.parse()?)
-    } else {
-        Err(anyhow!(
-            "Unable to retrieve path to data directory. Highscore can't be read."
-        ))
+    #[cfg(target_arch = "wasm32")]
+    {
+        0
     }
 }
 
-/// Save highscore to platform specific data directory (e.g.
-/// `$HOME/.local/share/diplopod/highscore`). Errors will be logged but otherwise ignored.
-fn save_highscore(highscore: Res<Highscore>) {
-    if let Err(e) = write_highscore_to_file(highscore.0) {
-        warn!("{}", e)
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD`.
+fn format_date(date: u64) -> String {
+    if date == 0 {
+        return "-".to_string();
     }
-}
 
-/// Writes the highscore to the platform specific data directory. The file and all necessary
-/// directories are created by the function.
-fn write_highscore_to_file(highscore: u16) -> Result<()> {
-    if let Some(projects_dirs) = ProjectDirs::from(QUALIFIER, ORGANIZATION, TITLE) {
-        let data_dir = projects_dirs.data_dir();
-        std::fs::create_dir_all(data_dir)?;
+    // conversion of days to a civil date as described in
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (date / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
 
-        let mut file = File::create(data_dir.join(HIGHSCORE))?;
-        write!(file, "{}", &highscore)?;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
 
-        Ok(())
-    } else {
-        Err(anyhow!(
-            "Unable to retrieve path to data directory. Highscore can't be read."
-        ))
-    }
+/// Formats a duration as `minutes:seconds`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
 }
 
 /// Removes the initial delay of the screen that ensures that keyboard and gamepad events are not processed
@@ -123,33 +204,20 @@ fn remove_initial_delay(mut commands: Commands) {
     commands.remove_resource::<InitialDelay>();
 }
 
-/// Forwards to the menu when any key is pressed after an initial delay.
-fn keyboard(
-    mut keyboard_event: MessageReader<KeyboardInput>,
-    mut game_state: ResMut<NextState<GameState>>,
-) {
-    for ev in keyboard_event.read() {
-        match ev.state {
-            ButtonState::Released => game_state.set(GameState::Menu),
-            ButtonState::Pressed => (),
-        }
+/// Forwards to the menu when confirm or back is pressed after an initial delay.
+fn navigate(actions: Res<ButtonInput<Action>>, mut game_state: ResMut<NextState<GameState>>) {
+    if actions.any_just_released([Action::Confirm, Action::Back]) {
+        game_state.set(GameState::Menu);
     }
 }
 
-/// Forwards to the menu when the A key of the gamepad is pressed after an initial delay.
-pub fn gamepad(gamepads: Query<&Gamepad>, mut game_state: ResMut<NextState<GameState>>) {
-    for gamepad in gamepads.iter() {
-        if gamepad.just_released(GamepadButton::South) {
-            game_state.set(GameState::Menu);
-        }
-    }
-}
-
-/// Creates the UI of the highscore screen.
+/// Creates the UI of the highscore screen. It shows the leaderboard of the rules and level of the
+/// last game.
 fn setup_highscore(
     mut commands: Commands,
-    highscore: Res<Highscore>,
+    leaderboard: Res<Leaderboard>,
     lastscore: Res<Lastscore>,
+    statistics: Res<Statistics>,
     asset_server: Res<AssetServer>,
 ) {
     let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
@@ -173,20 +241,6 @@ fn setup_highscore(
                     ..default()
                 })
                 .with_children(|parent| {
-                    parent.spawn((
-                        Text::new(TITLE),
-                        TextFont {
-                            font: font.clone(),
-                            font_size: FontSize::Px(128.0),
-                            ..default()
-                        },
-                        TextColor(TITLE_COLOR),
-                        Node {
-                            margin: UiRect::all(Val::Px(50.0)),
-                            ..default()
-                        },
-                    ));
-
                     parent.spawn((
                         Text::new("Highscore"),
                         TextFont {
@@ -196,35 +250,73 @@ fn setup_highscore(
                         },
                         TextColor(HEADLINE_COLOR),
                         Node {
-                            margin: UiRect::all(Val::Px(50.0)),
+                            margin: UiRect::all(Val::Px(25.0)),
                             ..default()
                         },
                     ));
 
+                    spawn_row(
+                        parent,
+                        &font,
+                        TABLE_HEADER_COLOR,
+                        ["", "Name", "Length", "Time", "Date", "Seed"].map(String::from),
+                    );
+
+                    let board = leaderboard.board(lastscore.rules, lastscore.level.as_deref());
+
+                    for (rank, (index, entry)) in board.enumerate() {
+                        let color = if leaderboard.last_entry == Some(index) {
+                            LAST_ENTRY_COLOR
+                        } else {
+                            HIGHSCORE_COLOR
+                        };
+
+                        spawn_row(
+                            parent,
+                            &font,
+                            color,
+                            [
+                                format!("{}.", rank + 1),
+                                entry.name.clone(),
+                                entry.length.to_string(),
+                                format_duration(entry.duration),
+                                format_date(entry.date),
+                                entry.seed.to_string(),
+                            ],
+                        );
+                    }
+
                     parent.spawn((
-                        Text::new(format!("Highscore {}", &highscore.0)),
+                        Text::new(format!(
+                            "Your last score was {} (seed {})",
+                            &lastscore.score, &lastscore.seed
+                        )),
                         TextFont {
                             font: font.clone(),
-                            font_size: FontSize::Px(64.0),
+                            font_size: FontSize::Px(32.0),
                             ..default()
                         },
-                        TextColor(HIGHSCORE_COLOR),
+                        TextColor(TABLE_HEADER_COLOR),
                         Node {
-                            margin: UiRect::all(Val::Px(25.0)),
+                            margin: UiRect::top(Val::Px(25.0)),
                             ..default()
                         },
                     ));
 
                     parent.spawn((
-                        Text::new(format!("Your last score was {}", &lastscore.0)),
+                        Text::new(format!(
+                            "{} games played in {}",
+                            statistics.games_played,
+                            format_duration(statistics.play_time)
+                        )),
                         TextFont {
                             font: font.clone(),
-                            font_size: FontSize::Px(64.0),
+                            font_size: FontSize::Px(24.0),
                             ..default()
                         },
-                        TextColor(HIGHSCORE_COLOR),
+                        TextColor(TABLE_HEADER_COLOR),
                         Node {
-                            margin: UiRect::all(Val::Px(25.0)),
+                            margin: UiRect::all(Val::Px(10.0)),
                             ..default()
                         },
                     ));
@@ -233,3 +325,151 @@ fn setup_highscore(
 
     commands.init_resource::<InitialDelay>();
 }
+
+/// Spawns a row of the leaderboard table.
+fn spawn_row(
+    parent: &mut ChildSpawnerCommands,
+    font: &FontSource,
+    color: Color,
+    columns: [String; 6],
+) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            ..default()
+        })
+        .with_children(|parent| {
+            for (text, width) in columns.into_iter().zip(COLUMN_WIDTHS) {
+                parent.spawn((
+                    Text::new(text),
+                    TextFont {
+                        font: font.clone(),
+                        font_size: FontSize::Px(24.0),
+                        ..default()
+                    },
+                    TextColor(color),
+                    Node {
+                        width: Val::Px(width),
+                        margin: UiRect::vertical(Val::Px(2.0)),
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, length: u16) -> Entry {
+        Entry {
+            name: name.to_string(),
+            date: 0,
+            length,
+            duration: Duration::ZERO,
+            seed: 0,
+            rules: Rules::default(),
+            level: None,
+        }
+    }
+
+    fn names(leaderboard: &Leaderboard) -> Vec<&str> {
+        leaderboard
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    /// A full leaderboard with the lengths 10, 20, ..., 100.
+    fn full_leaderboard() -> Leaderboard {
+        let mut leaderboard = Leaderboard::default();
+
+        for length in 1..=LEADERBOARD_SIZE as u16 {
+            leaderboard.insert(entry(&length.to_string(), length * 10));
+        }
+
+        leaderboard
+    }
+
+    #[test]
+    fn ties_go_behind_runs_of_equal_length() {
+        let mut leaderboard = Leaderboard::default();
+        leaderboard.insert(entry("AAA", 10));
+        leaderboard.insert(entry("BBB", 20));
+        leaderboard.insert(entry("CCC", 10));
+
+        assert_eq!(names(&leaderboard), ["BBB", "AAA", "CCC"]);
+        assert_eq!(leaderboard.last_entry, Some(2));
+        assert_eq!(leaderboard.best(Rules::default(), None), 20);
+    }
+
+    #[test]
+    fn full_leaderboard_drops_the_worst_run() {
+        let mut leaderboard = full_leaderboard();
+
+        assert!(leaderboard.qualifies(11, Rules::default(), None));
+        assert!(!leaderboard.qualifies(10, Rules::default(), None));
+
+        leaderboard.insert(entry("NEW", 55));
+
+        assert_eq!(leaderboard.entries.len(), LEADERBOARD_SIZE);
+        assert_eq!(leaderboard.entries[5].name, "NEW");
+        assert_eq!(leaderboard.last_entry, Some(5));
+        assert_eq!(leaderboard.entries.last().unwrap().length, 20);
+    }
+
+    #[test]
+    fn run_that_falls_off_the_board_is_no_last_entry() {
+        let mut leaderboard = full_leaderboard();
+
+        leaderboard.insert(entry("TIE", 10));
+
+        assert_eq!(leaderboard.entries.len(), LEADERBOARD_SIZE);
+        assert!(!names(&leaderboard).contains(&"TIE"));
+        assert_eq!(leaderboard.last_entry, None);
+    }
+
+    #[test]
+    fn empty_run_never_qualifies() {
+        assert!(!Leaderboard::default().qualifies(0, Rules::default(), None));
+        assert!(Leaderboard::default().qualifies(1, Rules::default(), None));
+    }
+
+    #[test]
+    fn runs_only_compete_with_the_same_rules_and_level() {
+        let mut leaderboard = full_leaderboard();
+        let wrap_around = Rules {
+            wrap_around: true,
+            ..default()
+        };
+
+        assert!(leaderboard.qualifies(5, wrap_around, None));
+        assert!(leaderboard.qualifies(5, Rules::default(), Some("levels/01.level")));
+
+        for length in [500, 5] {
+            leaderboard.insert(Entry {
+                rules: wrap_around,
+                ..entry("WRAP", length)
+            });
+        }
+
+        leaderboard.insert(Entry {
+            level: Some("levels/01.level".to_string()),
+            ..entry("LVL", 1000)
+        });
+
+        assert_eq!(
+            leaderboard.board(Rules::default(), None).count(),
+            LEADERBOARD_SIZE
+        );
+        assert_eq!(leaderboard.best(Rules::default(), None), 100);
+        assert_eq!(leaderboard.best(wrap_around, None), 500);
+        assert_eq!(
+            leaderboard.best(Rules::default(), Some("levels/01.level")),
+            1000
+        );
+        assert_eq!(leaderboard.entries.last().unwrap().name, "WRAP");
+    }
+}
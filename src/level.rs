@@ -0,0 +1,136 @@
+use anyhow::Result;
+use bevy::asset::{AssetLoader, LoadContext, LoadState, io::Reader};
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::campaign::{CAMPAIGN, CampaignLevel};
+use crate::editor::TestPlay;
+use crate::network::NetworkGame;
+use crate::replay::Playback;
+use crate::simulation::level::Level;
+
+/// Loads levels with custom wall layouts from `.level` files in the assets and waits for the
+/// level of the next game to be loaded before it starts.
+pub struct LevelPlugin;
+
+/// Asset source of the levels in the data directory (e.g. `user://levels/custom.level`).
+pub const USER_SOURCE: &str = "user";
+
+/// A level loaded from a `.level` file.
+#[derive(Asset, TypePath)]
+pub struct LevelAsset(pub Level);
+
+/// Path of the level within the assets (e.g. `levels/pillars.level`) that is played in the
+/// following games. Without a level the games are played in a bordered arena.
+#[derive(Default, Resource)]
+pub struct SelectedLevel(pub Option<String>);
+
+/// The level of the next game, which may still be loading.
+#[derive(Resource)]
+pub struct LevelHandle {
+    pub path: String,
+    pub handle: Handle<LevelAsset>,
+}
+
+#[derive(Default, TypePath)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<LevelAsset> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        Ok(LevelAsset(String::from_utf8(bytes)?.parse()?))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<LevelAsset>()
+            .init_asset_loader::<LevelLoader>()
+            .add_systems(OnEnter(GameState::Loading), load_level)
+            .add_systems(Update, start_game.run_if(in_state(GameState::Loading)))
+            .init_resource::<SelectedLevel>();
+    }
+}
+
+/// Starts to load the level of the replay that is played back, the level of the campaign or the
+/// selected level. The level that is tested in the editor is already loaded.
+fn load_level(
+    mut commands: Commands,
+    selected: Res<SelectedLevel>,
+    playback: Option<Res<Playback>>,
+    campaign_level: Option<Res<CampaignLevel>>,
+    test_play: Option<Res<TestPlay>>,
+    network: Option<Res<NetworkGame>>,
+    asset_server: Res<AssetServer>,
+) {
+    // the server plays games over the network in the bordered arena
+    if network.is_some() {
+        commands.remove_resource::<LevelHandle>();
+        return;
+    }
+
+    if let Some(test_play) = test_play {
+        commands.insert_resource(LevelHandle {
+            path: test_play.path.clone(),
+            handle: test_play.handle.clone(),
+        });
+        return;
+    }
+
+    let path = match (playback, campaign_level) {
+        (Some(playback), _) => playback.0.level().map(str::to_string),
+        (None, Some(campaign_level)) => Some(CAMPAIGN[campaign_level.0].to_string()),
+        (None, None) => selected.0.clone(),
+    };
+
+    match path {
+        Some(path) => commands.insert_resource(LevelHandle {
+            handle: asset_server.load(&path),
+            path,
+        }),
+        None => commands.remove_resource::<LevelHandle>(),
+    }
+}
+
+/// Starts the game once its level has been loaded. A level that can't be loaded is deselected
+/// and the player returns to the menu.
+fn start_game(
+    mut commands: Commands,
+    level: Option<Res<LevelHandle>>,
+    mut selected: ResMut<SelectedLevel>,
+    levels: Res<Assets<LevelAsset>>,
+    asset_server: Res<AssetServer>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let Some(level) = level else {
+        game_state.set(GameState::Game);
+        return;
+    };
+
+    if levels.contains(&level.handle) {
+        game_state.set(GameState::Game);
+    } else if let LoadState::Failed(e) = asset_server.load_state(&level.handle) {
+        warn!("Unable to load level: {}", e);
+
+        selected.0 = None;
+        commands.remove_resource::<LevelHandle>();
+        commands.remove_resource::<Playback>();
+        commands.remove_resource::<CampaignLevel>();
+        game_state.set(GameState::Menu);
+    }
+}
@@ -0,0 +1,5 @@
+//! The parts of diplopod that do not depend on Bevy. They are shared by the game and the
+//! `diplopod-server`, which runs games over the network.
+
+pub mod net;
+pub mod simulation;
@@ -1,14 +1,25 @@
+mod campaign;
+mod controls;
+mod editor;
 mod game;
 mod highscore;
+mod level;
 mod menu;
+mod name_entry;
+mod network;
+mod pause;
+mod replay;
+mod results;
+mod save;
+mod settings;
 
+use bevy::asset::io::AssetSourceBuilder;
 use bevy::window::{CursorOptions, PrimaryWindow};
 use bevy::{camera::ScalingMode, prelude::*};
+use diplopod::simulation;
 
-use crate::game::{
-    ANTIDOTE_COLOR, DIPLOPOD_COLOR, DIPLOPOD_IMMUNE_COLOR, DiplopodColors, FOOD_COLOR,
-    POISON_FILL_COLOR, POISON_OUTLINE_COLOR, SUPERFOOD_COLOR, WALL_COLOR,
-};
+use crate::game::DiplopodColors;
+use crate::settings::Settings;
 
 const TITLE: &str = "diplopod";
 const MAX_X: f32 = 1920.0;
@@ -18,8 +29,17 @@ const MAX_Y: f32 = 1200.0;
 enum GameState {
     #[default]
     Menu,
+    /// Waits for the level of the next game to be loaded.
+    Loading,
     Game,
+    NameEntry,
     Highscore,
+    LevelSelect,
+    Editor,
+    /// Shows how a game with several players ended.
+    Results,
+    /// Waits for the server to start a game over the network.
+    Connecting,
 }
 
 #[derive(Resource)]
@@ -33,24 +53,103 @@ struct Sounds {
 }
 
 fn main() {
-    App::new()
-        .add_plugins((
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: TITLE.into(),
-                    resolution: (1149, 645).into(),
-                    ..default()
-                }),
+    let playback = replay_from_args();
+    let server_address = arg_value("--connect");
+    let initial_state = if playback.is_some() {
+        GameState::Loading
+    } else if server_address.is_some() {
+        GameState::Connecting
+    } else {
+        GameState::default()
+    };
+
+    let mut app = App::new();
+
+    // levels created with the editor are loaded from the data directory
+    if save::PERSISTENT {
+        match save::data_dir() {
+            Ok(data_dir) => {
+                app.register_asset_source(
+                    level::USER_SOURCE,
+                    AssetSourceBuilder::platform_default(&data_dir.to_string_lossy(), None),
+                );
+            }
+            Err(e) => warn!("Unable to load levels from the data directory: {}", e),
+        }
+    }
+
+    app.add_plugins((
+        DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: TITLE.into(),
+                resolution: (1149, 645).into(),
                 ..default()
             }),
-            menu::MenuPlugin,
-            highscore::HighscorePlugin,
-            game::GamePlugin,
-        ))
-        .add_systems(Startup, setup)
-        .init_state::<GameState>()
-        .insert_resource(ClearColor(Color::BLACK))
-        .run();
+            ..default()
+        }),
+        menu::MenuPlugin,
+        highscore::HighscorePlugin,
+        name_entry::NameEntryPlugin,
+        game::GamePlugin,
+        replay::ReplayPlugin,
+        save::SavePlugin,
+        settings::SettingsPlugin,
+        controls::ControlsPlugin,
+        pause::PausePlugin,
+        level::LevelPlugin,
+        campaign::CampaignPlugin,
+        editor::EditorPlugin,
+        results::ResultsPlugin,
+        network::NetworkPlugin,
+    ))
+    .add_systems(Startup, setup)
+    .insert_resource(game::Seed(seed_from_args()))
+    .insert_resource(level::SelectedLevel(arg_value("--level")))
+    .insert_state(initial_state)
+    .insert_resource(ClearColor(Color::BLACK));
+
+    if let Some(replay) = playback {
+        app.insert_resource(replay::Playback(replay));
+    }
+
+    // the game connects to the server given on the command line (`--connect <address>`)
+    if let Some(address) = server_address {
+        app.insert_resource(network::ServerAddress(address));
+    }
+
+    app.run();
+}
+
+/// Returns the value following the given option on the command line.
+fn arg_value(option: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != option).nth(1)
+}
+
+/// Reads a fixed seed for all games from the command line (`--seed <number>`).
+fn seed_from_args() -> Option<u64> {
+    let seed = arg_value("--seed")?;
+
+    match seed.parse() {
+        Ok(seed) => Some(seed),
+        Err(e) => {
+            warn!("Ignoring invalid seed '{}': {}", seed, e);
+            None
+        }
+    }
+}
+
+/// Reads a replay that is played back instead of the first game from the command line
+/// (`--replay <file>`).
+fn replay_from_args() -> Option<simulation::replay::Replay> {
+    let path = arg_value("--replay")?;
+
+    match replay::read_replay_from_file(path.as_ref()) {
+        Ok(replay) => Some(replay),
+        Err(e) => {
+            warn!("Unable to read replay '{}': {}", path, e);
+            None
+        }
+    }
 }
 
 fn setup(
@@ -58,6 +157,7 @@ fn setup(
     asset_server: Res<AssetServer>,
     mut cursor_options: Single<&mut CursorOptions, With<PrimaryWindow>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    settings: Res<Settings>,
 ) {
     commands.spawn((
         Camera2d,
@@ -82,15 +182,19 @@ fn setup(
 
     cursor_options.visible = false;
 
+    // the colors are changed by the settings later on, so every material must be unique
+    let palette = settings.theme.palette();
+
     commands.insert_resource(DiplopodColors {
-        diplopod_normal: MeshMaterial2d(materials.add(DIPLOPOD_COLOR)),
-        diplopod_immune: MeshMaterial2d(materials.add(DIPLOPOD_IMMUNE_COLOR)),
-        antidote: MeshMaterial2d(materials.add(ANTIDOTE_COLOR)),
-        food: MeshMaterial2d(materials.add(FOOD_COLOR)),
-        poison_fill: MeshMaterial2d(materials.add(POISON_FILL_COLOR)),
-        poison_outline: MeshMaterial2d(materials.add(POISON_OUTLINE_COLOR)),
-        superfood: MeshMaterial2d(materials.add(SUPERFOOD_COLOR)),
-        wall: MeshMaterial2d(materials.add(WALL_COLOR)),
+        diplopod_normal: MeshMaterial2d(materials.add(palette.diplopod)),
+        diplopod_immune: MeshMaterial2d(materials.add(palette.diplopod_immune)),
+        diplopod_second: MeshMaterial2d(materials.add(palette.diplopod_second)),
+        antidote: MeshMaterial2d(materials.add(palette.antidote)),
+        food: MeshMaterial2d(materials.add(palette.food)),
+        poison_fill: MeshMaterial2d(materials.add(palette.poison_fill)),
+        poison_outline: MeshMaterial2d(materials.add(palette.poison_outline)),
+        superfood: MeshMaterial2d(materials.add(palette.superfood)),
+        wall: MeshMaterial2d(materials.add(palette.wall)),
     });
 }
 
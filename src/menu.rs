@@ -1,6 +1,11 @@
 use bevy::{app::AppExit, color::palettes::css::ANTIQUE_WHITE, prelude::*};
+use std::time::Duration;
 
 use crate::TITLE;
+use crate::controls::Action;
+use crate::game::SelectedMode;
+use crate::settings::SettingsScreen;
+use crate::simulation::Mode;
 
 use super::{GameState, despawn_screen};
 
@@ -11,7 +16,10 @@ impl Plugin for MenuPlugin {
         app.add_systems(OnEnter(GameState::Menu), setup_menu)
             .add_systems(
                 Update,
-                (gamepad, keyboard).run_if(in_state(GameState::Menu)),
+                ((navigate, choose_mode).run_if(menu_shown), hide_when_idle)
+                    .chain()
+                    .run_if(in_state(GameState::Menu))
+                    .run_if(in_state(SettingsScreen::Closed)),
             )
             .add_systems(OnExit(GameState::Menu), despawn_screen::<OnMenuScreen>)
             .insert_resource(Selected::default());
@@ -19,18 +27,32 @@ impl Plugin for MenuPlugin {
 }
 
 const TITLE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+/// Dims the game the computer plays behind the menu.
+const BACKGROUND_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
 const BUTTON_TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
 const BUTTON_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
 const BUTTON_SELECTED_BACKGROUND_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
 
+/// Time without input after which the menu is hidden, so that only the game in its background
+/// is shown, like on an arcade cabinet.
+const IDLE_DURATION: Duration = Duration::from_secs(30);
+
 #[derive(Component)]
 struct OnMenuScreen;
 
+/// Counts the time without input while the menu is shown.
+#[derive(Component)]
+struct Idle(Timer);
+
 #[derive(Component, Default, Debug, PartialEq)]
 pub enum MenuButton {
     #[default]
     Play,
+    Network,
+    Campaign,
+    Editor,
     Highscore,
+    Settings,
     Quit,
 }
 
@@ -38,15 +60,23 @@ impl MenuButton {
     fn previous(&self) -> Self {
         match *self {
             MenuButton::Play => MenuButton::Quit,
-            MenuButton::Highscore => MenuButton::Play,
-            MenuButton::Quit => MenuButton::Highscore,
+            MenuButton::Network => MenuButton::Play,
+            MenuButton::Campaign => MenuButton::Network,
+            MenuButton::Editor => MenuButton::Campaign,
+            MenuButton::Highscore => MenuButton::Editor,
+            MenuButton::Settings => MenuButton::Highscore,
+            MenuButton::Quit => MenuButton::Settings,
         }
     }
 
     fn next(&self) -> Self {
         match *self {
-            MenuButton::Play => MenuButton::Highscore,
-            MenuButton::Highscore => MenuButton::Quit,
+            MenuButton::Play => MenuButton::Network,
+            MenuButton::Network => MenuButton::Campaign,
+            MenuButton::Campaign => MenuButton::Editor,
+            MenuButton::Editor => MenuButton::Highscore,
+            MenuButton::Highscore => MenuButton::Settings,
+            MenuButton::Settings => MenuButton::Quit,
             MenuButton::Quit => MenuButton::Play,
         }
     }
@@ -55,29 +85,47 @@ impl MenuButton {
 #[derive(Default, Resource, Debug)]
 pub struct Selected(pub MenuButton);
 
-fn keyboard(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+/// The text of the play button, which names the selected mode.
+#[derive(Component)]
+struct PlayText;
+
+fn play_label(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Single => "< Play >",
+        Mode::Versus => "< Versus >",
+        Mode::Coop => "< Co-op >",
+        Mode::Computer => "< Computer >",
+    }
+}
+
+fn navigate(
+    actions: Res<ButtonInput<Action>>,
     mut selected: ResMut<Selected>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut settings_screen: ResMut<NextState<SettingsScreen>>,
     mut app_exit_events: MessageWriter<AppExit>,
     query: Query<(&mut BackgroundColor, &MenuButton)>,
 ) {
-    if keyboard_input.any_just_released([KeyCode::ArrowUp, KeyCode::KeyW, KeyCode::KeyK]) {
+    if actions.just_released(Action::Up) {
         selected.0 = selected.0.previous();
         update_selected_button(&selected.into(), query);
         return;
     }
 
-    if keyboard_input.any_just_released([KeyCode::ArrowDown, KeyCode::KeyS, KeyCode::KeyJ]) {
+    if actions.just_released(Action::Down) {
         selected.0 = selected.0.next();
         update_selected_button(&selected.into(), query);
         return;
     }
 
-    if keyboard_input.any_just_released([KeyCode::Enter, KeyCode::Space]) {
+    if actions.just_released(Action::Confirm) {
         match &selected.0 {
-            MenuButton::Play => game_state.set(GameState::Game),
+            MenuButton::Play => game_state.set(GameState::Loading),
+            MenuButton::Network => game_state.set(GameState::Connecting),
+            MenuButton::Campaign => game_state.set(GameState::LevelSelect),
+            MenuButton::Editor => game_state.set(GameState::Editor),
             MenuButton::Highscore => game_state.set(GameState::Highscore),
+            MenuButton::Settings => settings_screen.set(SettingsScreen::Settings),
             MenuButton::Quit => {
                 app_exit_events.write(AppExit::Success);
             }
@@ -85,34 +133,54 @@ fn keyboard(
     }
 }
 
-pub fn gamepad(
-    gamepads: Query<&Gamepad>,
-    mut selected: ResMut<Selected>,
-    query: Query<(&mut BackgroundColor, &MenuButton)>,
-    mut game_state: ResMut<NextState<GameState>>,
-    mut app_exit_events: MessageWriter<AppExit>,
+/// Chooses who plays with left and right while the play button is selected.
+fn choose_mode(
+    actions: Res<ButtonInput<Action>>,
+    selected: Res<Selected>,
+    mut mode: ResMut<SelectedMode>,
+    mut play_text: Query<&mut Text, With<PlayText>>,
 ) {
-    for gamepad in gamepads.iter() {
-        if gamepad.just_released(GamepadButton::DPadUp) {
-            selected.0 = selected.0.previous();
-            update_selected_button(&selected.into(), query);
-            return;
-        }
+    if selected.0 != MenuButton::Play || !actions.any_just_released([Action::Left, Action::Right]) {
+        return;
+    }
 
-        if gamepad.just_released(GamepadButton::DPadDown) {
-            selected.0 = selected.0.next();
-            update_selected_button(&selected.into(), query);
-            return;
-        }
+    let count = Mode::ALL.len();
+    let index = Mode::ALL.iter().position(|&m| m == mode.0).unwrap_or(0);
+    let index = if actions.just_released(Action::Left) {
+        (index + count - 1) % count
+    } else {
+        (index + 1) % count
+    };
 
-        if gamepad.just_released(GamepadButton::South) {
-            match &selected.0 {
-                MenuButton::Play => game_state.set(GameState::Game),
-                MenuButton::Highscore => game_state.set(GameState::Highscore),
-                MenuButton::Quit => {
-                    app_exit_events.write(AppExit::Success);
-                }
-            }
+    mode.0 = Mode::ALL[index];
+
+    for mut text in play_text.iter_mut() {
+        text.0 = play_label(mode.0).to_string();
+    }
+}
+
+fn menu_shown(menu: Query<&Visibility, With<OnMenuScreen>>) -> bool {
+    menu.iter()
+        .all(|visibility| visibility != Visibility::Hidden)
+}
+
+/// Hides the menu after a while without input and shows it again on any key or button. The
+/// input that shows the menu does nothing else.
+fn hide_when_idle(
+    actions: Res<ButtonInput<Action>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time<Real>>,
+    mut menu: Query<(&mut Idle, &mut Visibility), With<OnMenuScreen>>,
+) {
+    let input =
+        actions.get_just_released().next().is_some() || keys.get_just_released().next().is_some();
+
+    for (mut idle, mut visibility) in &mut menu {
+        if input {
+            idle.0.reset();
+            *visibility = Visibility::Inherited;
+        } else if idle.0.tick(time.delta()).just_finished() {
+            *visibility = Visibility::Hidden;
         }
     }
 }
@@ -130,11 +198,16 @@ fn update_selected_button(
     }
 }
 
-fn setup_menu(mut commands: Commands, selected: Res<Selected>, asset_server: Res<AssetServer>) {
+fn setup_menu(
+    mut commands: Commands,
+    selected: Res<Selected>,
+    mode: Res<SelectedMode>,
+    asset_server: Res<AssetServer>,
+) {
     let button_node = Node {
         width: Val::Px(340.0),
-        height: Val::Px(65.0),
-        margin: UiRect::all(Val::Px(20.0)),
+        height: Val::Px(55.0),
+        margin: UiRect::all(Val::Px(8.0)),
         padding: UiRect::all(Val::Px(45.0)),
         justify_content: JustifyContent::Center,
         align_items: AlignItems::Center,
@@ -152,6 +225,8 @@ fn setup_menu(mut commands: Commands, selected: Res<Selected>, asset_server: Res
                 justify_content: JustifyContent::Center,
                 ..default()
             },
+            BackgroundColor(BACKGROUND_COLOR),
+            Idle(Timer::new(IDLE_DURATION, TimerMode::Once)),
             OnMenuScreen,
         ))
         .with_children(|parent| {
@@ -171,7 +246,7 @@ fn setup_menu(mut commands: Commands, selected: Res<Selected>, asset_server: Res
                         },
                         TextColor(TITLE_COLOR),
                         Node {
-                            margin: UiRect::all(Val::Px(50.0)),
+                            margin: UiRect::all(Val::Px(20.0)),
                             ..default()
                         },
                     ));
@@ -185,10 +260,68 @@ fn setup_menu(mut commands: Commands, selected: Res<Selected>, asset_server: Res
                         ))
                         .with_children(|parent| {
                             parent.spawn((
-                                Text::new("Play"),
+                                Text::new(play_label(mode.0)),
+                                TextFont {
+                                    font: font.clone(),
+                                    font_size: FontSize::Px(56.0),
+                                    ..default()
+                                },
+                                TextColor(BUTTON_TEXT_COLOR),
+                                PlayText,
+                            ));
+                        });
+
+                    parent
+                        .spawn((
+                            Button,
+                            button_node.clone(),
+                            background_color(&selected.0, &MenuButton::Network),
+                            MenuButton::Network,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Network"),
                                 TextFont {
                                     font: font.clone(),
-                                    font_size: FontSize::Px(64.0),
+                                    font_size: FontSize::Px(56.0),
+                                    ..default()
+                                },
+                                TextColor(BUTTON_TEXT_COLOR),
+                            ));
+                        });
+
+                    parent
+                        .spawn((
+                            Button,
+                            button_node.clone(),
+                            background_color(&selected.0, &MenuButton::Campaign),
+                            MenuButton::Campaign,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Campaign"),
+                                TextFont {
+                                    font: font.clone(),
+                                    font_size: FontSize::Px(56.0),
+                                    ..default()
+                                },
+                                TextColor(BUTTON_TEXT_COLOR),
+                            ));
+                        });
+
+                    parent
+                        .spawn((
+                            Button,
+                            button_node.clone(),
+                            background_color(&selected.0, &MenuButton::Editor),
+                            MenuButton::Editor,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Editor"),
+                                TextFont {
+                                    font: font.clone(),
+                                    font_size: FontSize::Px(56.0),
                                     ..default()
                                 },
                                 TextColor(BUTTON_TEXT_COLOR),
@@ -207,7 +340,26 @@ fn setup_menu(mut commands: Commands, selected: Res<Selected>, asset_server: Res
                                 Text::new("Highscore"),
                                 TextFont {
                                     font: font.clone(),
-                                    font_size: FontSize::Px(64.0),
+                                    font_size: FontSize::Px(56.0),
+                                    ..default()
+                                },
+                                TextColor(BUTTON_TEXT_COLOR),
+                            ));
+                        });
+
+                    parent
+                        .spawn((
+                            Button,
+                            button_node.clone(),
+                            background_color(&selected.0, &MenuButton::Settings),
+                            MenuButton::Settings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Settings"),
+                                TextFont {
+                                    font: font.clone(),
+                                    font_size: FontSize::Px(56.0),
                                     ..default()
                                 },
                                 TextColor(BUTTON_TEXT_COLOR),
@@ -226,7 +378,7 @@ fn setup_menu(mut commands: Commands, selected: Res<Selected>, asset_server: Res
                                 Text::new("Quit"),
                                 TextFont {
                                     font: font.clone(),
-                                    font_size: FontSize::Px(64.0),
+                                    font_size: FontSize::Px(56.0),
                                     ..default()
                                 },
                                 TextColor(BUTTON_TEXT_COLOR),
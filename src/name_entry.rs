@@ -0,0 +1,281 @@
+use bevy::{
+    color::palettes::css::ANTIQUE_WHITE,
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    prelude::*,
+};
+
+use crate::controls::Action;
+use crate::highscore::{Entry, InitialDelay, Lastscore, Leaderboard, now};
+
+use super::{GameState, despawn_screen};
+
+/// Adds a screen to enter the initials of the player after a game that made it into the
+/// leaderboard.
+pub struct NameEntryPlugin;
+
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const LETTER_COLOR: Color = Color::WHITE;
+const LETTER_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const LETTER_SELECTED_BACKGROUND_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+
+const NAME_LENGTH: usize = 3;
+
+#[derive(Component)]
+struct OnNameEntryScreen;
+
+/// Displays the letter of the name at the given index.
+#[derive(Component)]
+struct Letter(usize);
+
+/// The initials that are currently entered. They are kept for the next entry of the session.
+#[derive(Resource)]
+struct Name {
+    letters: [char; NAME_LENGTH],
+    selected: usize,
+}
+
+impl Default for Name {
+    fn default() -> Self {
+        Self {
+            letters: ['A'; NAME_LENGTH],
+            selected: 0,
+        }
+    }
+}
+
+impl Name {
+    fn change_letter(&mut self, delta: i8) {
+        let letter = &mut self.letters[self.selected];
+        *letter = (b'A' + ((*letter as u8 - b'A') as i8 + delta).rem_euclid(26) as u8) as char;
+    }
+
+    fn set_letter(&mut self, letter: char) {
+        self.letters[self.selected] = letter;
+        self.select_next();
+    }
+
+    fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(NAME_LENGTH - 1);
+    }
+
+    fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+impl Plugin for NameEntryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::NameEntry), setup_name_entry)
+            .add_systems(
+                Update,
+                (enter_name, update_letters)
+                    .chain()
+                    .run_if(in_state(GameState::NameEntry))
+                    .run_if(not(resource_exists::<InitialDelay>)),
+            )
+            .add_systems(
+                OnExit(GameState::NameEntry),
+                despawn_screen::<OnNameEntryScreen>,
+            )
+            .init_resource::<Name>();
+    }
+}
+
+/// Adds the last game with the entered name to the leaderboard and forwards to the highscore
+/// screen.
+fn confirm(
+    name: &Name,
+    lastscore: &Lastscore,
+    leaderboard: &mut Leaderboard,
+    game_state: &mut NextState<GameState>,
+) {
+    leaderboard.insert(Entry {
+        name: name.letters.iter().collect(),
+        date: now(),
+        length: lastscore.score,
+        duration: lastscore.duration,
+        seed: lastscore.seed,
+        rules: lastscore.rules,
+        level: lastscore.level.clone(),
+    });
+
+    game_state.set(GameState::Highscore);
+}
+
+/// Letters are typed on the keyboard or chosen with up and down, the other letters are selected
+/// with left, right and back. The name is entered with confirm.
+fn enter_name(
+    actions: Res<ButtonInput<Action>>,
+    mut keyboard_event: MessageReader<KeyboardInput>,
+    mut name: ResMut<Name>,
+    lastscore: Res<Lastscore>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let mut typed = false;
+
+    for ev in keyboard_event.read() {
+        if ev.state != ButtonState::Pressed {
+            continue;
+        }
+
+        if let Key::Character(text) = &ev.logical_key
+            && let Some(letter) = text.chars().next().filter(char::is_ascii_alphabetic)
+        {
+            name.set_letter(letter.to_ascii_uppercase());
+            typed = true;
+        }
+    }
+
+    // keys that type a letter may be bound to an action as well
+    if typed {
+        return;
+    }
+
+    if actions.just_pressed(Action::Up) {
+        name.change_letter(1);
+    }
+
+    if actions.just_pressed(Action::Down) {
+        name.change_letter(-1);
+    }
+
+    if actions.any_just_pressed([Action::Left, Action::Back]) {
+        name.select_previous();
+    }
+
+    if actions.just_pressed(Action::Right) {
+        name.select_next();
+    }
+
+    if actions.just_released(Action::Confirm) {
+        confirm(&name, &lastscore, &mut leaderboard, &mut game_state);
+    }
+}
+
+fn update_letters(name: Res<Name>, mut letters: Query<(&Letter, &mut Text, &mut BackgroundColor)>) {
+    if !name.is_changed() {
+        return;
+    }
+
+    for (letter, mut text, mut background_color) in letters.iter_mut() {
+        text.0 = name.letters[letter.0].to_string();
+        background_color.0 = letter_background_color(&name, letter.0);
+    }
+}
+
+fn letter_background_color(name: &Name, index: usize) -> Color {
+    if name.selected == index {
+        LETTER_SELECTED_BACKGROUND_COLOR
+    } else {
+        LETTER_BACKGROUND_COLOR
+    }
+}
+
+/// Creates the UI of the name entry screen.
+fn setup_name_entry(
+    mut commands: Commands,
+    mut name: ResMut<Name>,
+    lastscore: Res<Lastscore>,
+    asset_server: Res<AssetServer>,
+) {
+    name.selected = 0;
+
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            OnNameEntryScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("New highscore"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(64.0),
+                            ..default()
+                        },
+                        TextColor(HEADLINE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+
+                    parent.spawn((
+                        Text::new(format!("Your score is {}", &lastscore.score)),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(32.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+
+                    parent
+                        .spawn(Node {
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for index in 0..NAME_LENGTH {
+                                parent.spawn((
+                                    Text::new(name.letters[index].to_string()),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(96.0),
+                                        ..default()
+                                    },
+                                    TextColor(LETTER_COLOR),
+                                    TextLayout::justify(Justify::Center),
+                                    Node {
+                                        width: Val::Px(100.0),
+                                        margin: UiRect::all(Val::Px(10.0)),
+                                        ..default()
+                                    },
+                                    BackgroundColor(letter_background_color(&name, index)),
+                                    Letter(index),
+                                ));
+                            }
+                        });
+
+                    parent.spawn((
+                        Text::new("Enter your initials"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(32.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+
+    commands.init_resource::<InitialDelay>();
+}
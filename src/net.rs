@@ -0,0 +1,264 @@
+//! Messages between the `diplopod-server` and the games of the players.
+//!
+//! Games over the network are played in lockstep: every player sends the direction of its
+//! diplopod to the server, which takes the latest direction of every player once per tick,
+//! advances its own [`Simulation`](crate::simulation::Simulation) and sends the input of the
+//! tick to all players. As the simulation is deterministic, every game simulates the same ticks
+//! as the server, but only once their input has arrived.
+//!
+//! Every message is a single UDP datagram of plain text. A lost message is not resent as such:
+//! the players repeat their messages and the server always sends all ticks a player is missing.
+
+use anyhow::{Context, Result, anyhow};
+use std::time::Duration;
+use std::{fmt, str::FromStr};
+
+use crate::simulation::replay::Replay;
+use crate::simulation::{Direction, Input, MAX_PLAYERS, Rules};
+
+/// Port the server listens on unless another address is given.
+pub const DEFAULT_PORT: u16 = 27183;
+
+/// Maximum size of a message, which fits into a datagram on every network.
+pub const MAX_MESSAGE_SIZE: usize = 1200;
+
+/// Maximum number of ticks in a message. A player who is missing more ticks gets them with the
+/// following messages.
+pub const MAX_TICKS: usize = 32;
+
+/// Time without a message after which the other side is regarded as gone.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A message from a player to the server.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClientMessage {
+    /// Asks for a place in the next game. It is repeated until the game starts.
+    Join,
+    /// The direction of the player and the number of ticks received so far.
+    Input { received: u64, direction: Direction },
+    /// The player quits the game.
+    Leave,
+}
+
+/// A message from the server to a player.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ServerMessage {
+    /// The player has joined, but the game waits for more players.
+    Waiting { player: usize },
+    /// The game has started with the given seed and rules.
+    Start {
+        player: usize,
+        seed: u64,
+        rules: Rules,
+    },
+    /// The input of consecutive ticks, starting with the tick `first`.
+    Ticks { first: u64, inputs: Vec<Input> },
+    /// The game was ended because a player left.
+    Abort,
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessage::Join => write!(f, "join"),
+            ClientMessage::Input {
+                received,
+                direction,
+            } => write!(f, "input {} {} {}", received, direction.x, direction.y),
+            ClientMessage::Leave => write!(f, "leave"),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, values) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+
+        match name {
+            "join" => Ok(ClientMessage::Join),
+            "leave" => Ok(ClientMessage::Leave),
+            "input" => match numbers(values)?[..] {
+                [received, x, y] if received >= 0 => Ok(ClientMessage::Input {
+                    received: received as u64,
+                    direction: Direction::new(x as i32, y as i32),
+                }),
+                _ => Err(anyhow!("Invalid input '{}'", values)),
+            },
+            _ => Err(anyhow!("Unknown message '{}'", name)),
+        }
+    }
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::Waiting { player } => write!(f, "waiting {}", player),
+            // the seed and the rules are written like the header of a replay
+            ServerMessage::Start {
+                player,
+                seed,
+                rules,
+            } => write!(f, "start {}\n{}", player, Replay::new(*seed, *rules, None)),
+            ServerMessage::Ticks { first, inputs } => {
+                write!(f, "ticks {}", first)?;
+
+                for input in inputs {
+                    writeln!(f)?;
+
+                    for (player, direction) in input.directions.iter().enumerate() {
+                        if player > 0 {
+                            write!(f, " ")?;
+                        }
+
+                        write!(f, "{} {}", direction.x, direction.y)?;
+                    }
+                }
+
+                Ok(())
+            }
+            ServerMessage::Abort => write!(f, "abort"),
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first_line, rest) = s.split_once('\n').unwrap_or((s, ""));
+        let (name, value) = first_line
+            .trim()
+            .split_once(' ')
+            .unwrap_or((first_line.trim(), ""));
+
+        match name {
+            "waiting" => Ok(ServerMessage::Waiting {
+                player: parse_player(value)?,
+            }),
+            "start" => {
+                let replay: Replay = rest.parse().context("Invalid rules of game")?;
+
+                Ok(ServerMessage::Start {
+                    player: parse_player(value)?,
+                    seed: replay.seed(),
+                    rules: replay.rules(),
+                })
+            }
+            "ticks" => {
+                let first = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid tick '{}'", value))?;
+
+                let inputs = rest.lines().map(parse_input).collect::<Result<Vec<_>>>()?;
+
+                if first == 0 || inputs.len() > MAX_TICKS {
+                    return Err(anyhow!("Invalid ticks {} to {}", first, inputs.len()));
+                }
+
+                Ok(ServerMessage::Ticks { first, inputs })
+            }
+            "abort" => Ok(ServerMessage::Abort),
+            _ => Err(anyhow!("Unknown message '{}'", name)),
+        }
+    }
+}
+
+fn numbers(values: &str) -> Result<Vec<i64>> {
+    values
+        .split_whitespace()
+        .map(|value| value.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid numbers '{}'", values))
+}
+
+fn parse_player(value: &str) -> Result<usize> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&player| player < MAX_PLAYERS)
+        .ok_or_else(|| anyhow!("Invalid player '{}'", value))
+}
+
+/// Reads the directions of all players in a tick.
+fn parse_input(line: &str) -> Result<Input> {
+    let values = numbers(line)?;
+
+    if values.len() != 2 * MAX_PLAYERS {
+        return Err(anyhow!("Invalid input '{}'", line));
+    }
+
+    let mut input = Input::default();
+
+    for (player, direction) in values.chunks(2).enumerate() {
+        input.directions[player] = Direction::new(direction[0] as i32, direction[1] as i32);
+    }
+
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Mode, Movement};
+
+    #[test]
+    fn client_messages_survive_a_round_trip() {
+        for message in [
+            ClientMessage::Join,
+            ClientMessage::Input {
+                received: 42,
+                direction: Direction::new(-1, 1),
+            },
+            ClientMessage::Leave,
+        ] {
+            assert_eq!(
+                message.to_string().parse::<ClientMessage>().unwrap(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn server_messages_survive_a_round_trip() {
+        let mut input = Input::default();
+        input.directions[0] = Direction::new(0, -1);
+        input.directions[1] = Direction::new(1, 1);
+
+        for message in [
+            ServerMessage::Waiting { player: 1 },
+            ServerMessage::Start {
+                player: 0,
+                seed: 7,
+                rules: Rules {
+                    movement: Movement::FourWay,
+                    mode: Mode::Coop,
+                    ..Rules::default()
+                },
+            },
+            ServerMessage::Ticks {
+                first: 3,
+                inputs: vec![Input::default(), input],
+            },
+            ServerMessage::Abort,
+        ] {
+            assert_eq!(
+                message.to_string().parse::<ServerMessage>().unwrap(),
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_messages_are_rejected() {
+        assert!("hello".parse::<ClientMessage>().is_err());
+        assert!("input -1 0 1".parse::<ClientMessage>().is_err());
+        assert!("input 1 0".parse::<ClientMessage>().is_err());
+        assert!("waiting 2".parse::<ServerMessage>().is_err());
+        assert!("ticks 0\n0 1 1 0".parse::<ServerMessage>().is_err());
+        assert!("ticks 1\n0 1".parse::<ServerMessage>().is_err());
+    }
+}
@@ -0,0 +1,442 @@
+use anyhow::{Result, anyhow};
+use bevy::color::palettes::css::ANTIQUE_WHITE;
+use bevy::prelude::*;
+use std::io::ErrorKind;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use crate::controls::Action;
+use crate::game::diplopod::DiplopodHead;
+use crate::game::{AdvanceSystems, CurrentGame};
+use crate::settings::Settings;
+use crate::simulation::{Direction, Input, Rules};
+use diplopod::net::{ClientMessage, DEFAULT_PORT, MAX_MESSAGE_SIZE, ServerMessage, TIMEOUT};
+
+use super::{GameState, despawn_screen};
+
+/// Plays games over the network with a `diplopod-server`. The server decides the input of every
+/// tick and the game only advances once it has arrived, so that every player simulates the same
+/// game.
+pub struct NetworkPlugin;
+
+/// Time between two repetitions of a message, in case it got lost.
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::Connecting),
+            (setup_connecting, connect).chain(),
+        )
+        .add_systems(
+            Update,
+            (
+                cancel.run_if(in_state(GameState::Connecting)),
+                join.run_if(in_state(GameState::Connecting))
+                    .run_if(resource_exists::<Connection>),
+                keep_alive
+                    .run_if(in_state(GameState::Results))
+                    .run_if(resource_exists::<Connection>)
+                    .run_if(resource_exists::<NetworkGame>),
+            ),
+        )
+        .add_systems(
+            OnExit(GameState::Connecting),
+            despawn_screen::<OnConnectingScreen>,
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                receive_ticks.before(AdvanceSystems),
+                send_input.after(AdvanceSystems),
+            )
+                .run_if(in_state(GameState::Game))
+                .run_if(resource_exists::<NetworkGame>),
+        )
+        .add_systems(OnEnter(GameState::Menu), disconnect)
+        .init_resource::<ServerAddress>();
+    }
+}
+
+/// Address of the server that games over the network are played on. It is given with
+/// `--connect` and defaults to a server on the same machine.
+#[derive(Resource)]
+pub struct ServerAddress(pub String);
+
+impl Default for ServerAddress {
+    fn default() -> Self {
+        Self(format!("127.0.0.1:{}", DEFAULT_PORT))
+    }
+}
+
+/// The connection to the server, which is open from joining a game until the player returns to
+/// the menu.
+#[derive(Resource)]
+pub struct Connection {
+    socket: UdpSocket,
+    /// Time of the last message from the server.
+    last_received: Duration,
+}
+
+impl Connection {
+    /// Opens a connection to the server at the given address (e.g. `127.0.0.1:27183`). Nothing
+    /// is sent until the player joins a game.
+    pub fn open(address: &str) -> Result<Self> {
+        let server = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("Unknown address"))?;
+
+        let socket = if server.is_ipv4() {
+            UdpSocket::bind("0.0.0.0:0")?
+        } else {
+            UdpSocket::bind("[::]:0")?
+        };
+
+        socket.connect(server)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            last_received: Duration::ZERO,
+        })
+    }
+
+    fn send(&self, message: ClientMessage) {
+        if let Err(e) = self.socket.send(message.to_string().as_bytes()) {
+            warn!("Unable to send to the server: {}", e);
+        }
+    }
+
+    /// Returns the next message that arrived from the server.
+    fn receive(&mut self, now: Duration) -> Option<ServerMessage> {
+        let mut buffer = [0; MAX_MESSAGE_SIZE];
+
+        loop {
+            match self.socket.recv(&mut buffer) {
+                Ok(length) => match std::str::from_utf8(&buffer[..length])
+                    .map_err(anyhow::Error::from)
+                    .and_then(str::parse)
+                {
+                    Ok(message) => {
+                        self.last_received = now;
+                        return Some(message);
+                    }
+                    Err(e) => warn!("Ignoring invalid message from the server: {:#}", e),
+                },
+                // a server that is not running yet is reported as a refused connection
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    return None;
+                }
+                Err(e) => {
+                    warn!("Unable to receive from the server: {}", e);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// A game over the network that was started by the server.
+#[derive(Resource)]
+pub struct NetworkGame {
+    /// The player on this machine, the others play on their own.
+    pub player: usize,
+    pub seed: u64,
+    pub rules: Rules,
+    /// The input of every tick the server has sent so far.
+    inputs: Vec<Input>,
+    /// The tick after which the direction of the player was sent last.
+    sent: Option<u64>,
+    direction: Direction,
+}
+
+impl NetworkGame {
+    /// The input of the given tick, if it has arrived already.
+    pub fn input(&self, tick: u64) -> Option<Input> {
+        let index = tick.checked_sub(1)?;
+        self.inputs.get(index as usize).copied()
+    }
+}
+
+/// Returns `true` if the input of the next tick of a game over the network has arrived.
+pub fn input_received(game: Res<CurrentGame>, network: Option<Res<NetworkGame>>) -> bool {
+    network.is_some_and(|network| network.input(game.ticks() + 1).is_some())
+}
+
+#[derive(Component)]
+struct OnConnectingScreen;
+
+#[derive(Component)]
+struct StatusText;
+
+fn setup_connecting(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
+    commands.remove_resource::<NetworkGame>();
+
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            OnConnectingScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Connecting"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(96.0),
+                            ..default()
+                        },
+                        TextColor(HEADLINE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(30.0)),
+                            ..default()
+                        },
+                    ));
+
+                    parent.spawn((
+                        Text::new("Waiting for the server"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(40.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        StatusText,
+                    ));
+
+                    parent.spawn((
+                        Text::new(format!(
+                            "Go back with {}",
+                            settings.bindings.hint(Action::Back)
+                        )),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(24.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(30.0)),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
+
+/// Repeats the request to join a game until it starts.
+#[derive(Resource)]
+struct JoinTimer(Timer);
+
+/// Opens the connection to the server, unless it is still open for a rematch. A failure is shown
+/// instead of the status.
+fn connect(
+    mut commands: Commands,
+    address: Res<ServerAddress>,
+    connection: Option<Res<Connection>>,
+    mut status: Query<&mut Text, With<StatusText>>,
+) {
+    // the first request is sent right away
+    let mut timer = Timer::new(RESEND_INTERVAL, TimerMode::Repeating);
+    timer.set_elapsed(RESEND_INTERVAL);
+    commands.insert_resource(JoinTimer(timer));
+
+    if connection.is_some() {
+        return;
+    }
+
+    match Connection::open(&address.0) {
+        Ok(connection) => commands.insert_resource(connection),
+        Err(e) => {
+            warn!("Unable to connect to '{}': {:#}", address.0, e);
+
+            for mut text in status.iter_mut() {
+                text.0 = format!("Unable to connect to {}: {:#}", address.0, e);
+            }
+        }
+    }
+}
+
+/// Returns to the menu, which also leaves the server.
+fn cancel(actions: Res<ButtonInput<Action>>, mut game_state: ResMut<NextState<GameState>>) {
+    if actions.just_pressed(Action::Back) {
+        game_state.set(GameState::Menu);
+    }
+}
+
+/// Asks the server for a place in the next game until it starts.
+fn join(
+    mut commands: Commands,
+    mut connection: ResMut<Connection>,
+    mut status: Query<&mut Text, With<StatusText>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    time: Res<Time<Real>>,
+    mut timer: ResMut<JoinTimer>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        connection.send(ClientMessage::Join);
+    }
+
+    while let Some(message) = connection.receive(time.elapsed()) {
+        match message {
+            ServerMessage::Waiting { player } => {
+                for mut text in status.iter_mut() {
+                    text.0 = format!("You are player {}, waiting for the others", player + 1);
+                }
+            }
+
+            ServerMessage::Start {
+                player,
+                seed,
+                rules,
+            } => {
+                commands.insert_resource(NetworkGame {
+                    player,
+                    seed,
+                    rules,
+                    inputs: Vec::new(),
+                    sent: None,
+                    direction: Direction::NONE,
+                });
+
+                game_state.set(GameState::Loading);
+                return;
+            }
+
+            ServerMessage::Ticks { .. } | ServerMessage::Abort => {}
+        }
+    }
+}
+
+/// Collects the input of the ticks the server sent and leaves the game if it was aborted or the
+/// server is gone.
+fn receive_ticks(
+    mut connection: ResMut<Connection>,
+    mut network: ResMut<NetworkGame>,
+    mut game_state: ResMut<NextState<GameState>>,
+    time: Res<Time<Real>>,
+) {
+    while let Some(message) = connection.receive(time.elapsed()) {
+        match message {
+            ServerMessage::Ticks { first, inputs } => {
+                let known = network.inputs.len() as u64;
+
+                // ticks that arrived before are skipped, a gap is filled by the next message
+                if first <= known + 1 {
+                    network
+                        .inputs
+                        .extend(inputs.into_iter().skip((known + 1 - first) as usize));
+                }
+            }
+
+            ServerMessage::Abort => {
+                warn!("The game was aborted, because another player left");
+                game_state.set(GameState::Menu);
+                return;
+            }
+
+            ServerMessage::Waiting { .. } | ServerMessage::Start { .. } => {}
+        }
+    }
+
+    if time.elapsed().saturating_sub(connection.last_received) > TIMEOUT {
+        warn!("Lost the connection to the server");
+        game_state.set(GameState::Menu);
+    }
+}
+
+/// Sends the direction of the player once per tick. The message is repeated in case it got lost,
+/// which also tells the server about missing ticks.
+fn send_input(
+    connection: Res<Connection>,
+    mut network: ResMut<NetworkGame>,
+    game: Res<CurrentGame>,
+    mut heads: Query<&mut DiplopodHead>,
+    time: Res<Time<Real>>,
+    mut timer: Local<Timer>,
+) {
+    if timer.duration() != RESEND_INTERVAL {
+        timer.set_duration(RESEND_INTERVAL);
+        timer.set_mode(TimerMode::Repeating);
+    }
+
+    let resend = timer.tick(time.delta()).just_finished();
+
+    if network.sent != Some(game.ticks()) {
+        let player = network.player;
+
+        if let Some(mut head) = heads.iter_mut().find(|head| head.player == player) {
+            let direction = head.next_direction();
+            network.direction = Direction::new(direction.x as i32, direction.y as i32);
+        }
+
+        network.sent = Some(game.ticks());
+    } else if !resend {
+        return;
+    }
+
+    timer.reset();
+
+    connection.send(ClientMessage::Input {
+        received: network.inputs.len() as u64,
+        direction: network.direction,
+    });
+}
+
+/// Tells the server that the player is still there while the results of a game are shown, so
+/// that the seat is kept for a rematch. This also confirms that the end of the game has arrived.
+fn keep_alive(
+    connection: Res<Connection>,
+    network: Res<NetworkGame>,
+    time: Res<Time<Real>>,
+    mut timer: Local<Timer>,
+) {
+    if timer.duration() != RESEND_INTERVAL {
+        timer.set_duration(RESEND_INTERVAL);
+        timer.set_mode(TimerMode::Repeating);
+    }
+
+    if timer.tick(time.delta()).just_finished() {
+        connection.send(ClientMessage::Input {
+            received: network.inputs.len() as u64,
+            direction: Direction::NONE,
+        });
+    }
+}
+
+/// Leaves the server when the player returns to the menu. The address is kept for the next game.
+fn disconnect(mut commands: Commands, connection: Option<Res<Connection>>) {
+    if let Some(connection) = connection {
+        connection.send(ClientMessage::Leave);
+        commands.remove_resource::<Connection>();
+    }
+
+    commands.remove_resource::<NetworkGame>();
+}
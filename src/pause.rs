@@ -0,0 +1,290 @@
+use bevy::input::gamepad::GamepadConnectionEvent;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+
+use crate::controls::Action;
+use crate::network::NetworkGame;
+use crate::settings::SettingsScreen;
+
+use super::{GameState, despawn_screen};
+
+/// Pauses the running game on request or when the player is distracted and shows a menu to
+/// resume, restart or leave the game. A game over the network goes on behind the menu.
+pub struct PausePlugin;
+
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, SubStates)]
+#[source(GameState = GameState::Game)]
+pub(crate) enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_sub_state::<PauseState>()
+            .add_systems(OnEnter(PauseState::Paused), (pause, setup_pause_menu))
+            .add_systems(
+                OnExit(PauseState::Paused),
+                (unpause, despawn_screen::<OnPauseScreen>),
+            )
+            .add_systems(
+                Update,
+                (
+                    request_pause.run_if(in_state(PauseState::Running)),
+                    navigate
+                        .run_if(in_state(PauseState::Paused))
+                        .run_if(in_state(SettingsScreen::Closed)),
+                ),
+            )
+            .init_resource::<SelectedPauseButton>();
+    }
+}
+
+const TITLE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const BACKGROUND_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.7);
+const BUTTON_TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const BUTTON_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const BUTTON_SELECTED_BACKGROUND_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+
+#[derive(Component)]
+struct OnPauseScreen;
+
+#[derive(Clone, Copy, Component, Debug, PartialEq)]
+enum PauseButton {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+}
+
+impl PauseButton {
+    const ALL: [Self; 4] = [Self::Resume, Self::Restart, Self::Settings, Self::Quit];
+
+    /// A game over the network can neither be restarted nor wait for the settings.
+    const NETWORK: [Self; 2] = [Self::Resume, Self::Quit];
+
+    fn all(network: bool) -> &'static [Self] {
+        if network { &Self::NETWORK } else { &Self::ALL }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            PauseButton::Resume => "Resume",
+            PauseButton::Restart => "Restart",
+            PauseButton::Settings => "Settings",
+            PauseButton::Quit => "Quit to Menu",
+        }
+    }
+}
+
+#[derive(Default, Resource)]
+struct SelectedPauseButton(usize);
+
+/// Pauses the game when the player asks for it, the window loses the focus or a gamepad is
+/// disconnected. The other players of a game over the network don't wait, so it only shows the
+/// menu when the player asks for it.
+fn request_pause(
+    actions: Res<ButtonInput<Action>>,
+    mut focus_reader: MessageReader<WindowFocused>,
+    mut connection_reader: MessageReader<GamepadConnectionEvent>,
+    network: Option<Res<NetworkGame>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+) {
+    let unfocused = focus_reader.read().any(|event| !event.focused);
+    let disconnected = connection_reader.read().any(|event| event.disconnected());
+    let distracted = (unfocused || disconnected) && network.is_none();
+
+    if actions.just_pressed(Action::Pause) || distracted {
+        pause_state.set(PauseState::Paused);
+    }
+}
+
+fn pause(
+    mut time: ResMut<Time<Virtual>>,
+    sounds: Query<&AudioSink>,
+    network: Option<Res<NetworkGame>>,
+) {
+    if network.is_some() {
+        return;
+    }
+
+    for sound in sounds.iter() {
+        sound.pause();
+    }
+    time.pause();
+}
+
+fn unpause(mut time: ResMut<Time<Virtual>>, sounds: Query<&AudioSink>) {
+    for sound in sounds.iter() {
+        sound.play();
+    }
+    time.unpause();
+}
+
+fn navigate(
+    actions: Res<ButtonInput<Action>>,
+    mut selected: ResMut<SelectedPauseButton>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+    mut settings_screen: ResMut<NextState<SettingsScreen>>,
+    mut buttons: Query<(&mut BackgroundColor, &PauseButton)>,
+    network: Option<Res<NetworkGame>>,
+) {
+    let all = PauseButton::all(network.is_some());
+    let count = all.len();
+
+    // a key that both confirms and pauses, like Space, chooses the selected button
+    if actions.just_pressed(Action::Confirm) {
+        match all[selected.0] {
+            PauseButton::Resume => pause_state.set(PauseState::Running),
+            PauseButton::Restart => game_state.set(GameState::Loading),
+            PauseButton::Settings => settings_screen.set(SettingsScreen::Settings),
+            PauseButton::Quit => game_state.set(GameState::Menu),
+        }
+        return;
+    }
+
+    if actions.any_just_pressed([Action::Pause, Action::Back]) {
+        pause_state.set(PauseState::Running);
+        return;
+    }
+
+    if actions.just_pressed(Action::Up) {
+        selected.0 = (selected.0 + count - 1) % count;
+    }
+
+    if actions.just_pressed(Action::Down) {
+        selected.0 = (selected.0 + 1) % count;
+    }
+
+    for (mut background_color, button) in buttons.iter_mut() {
+        background_color.0 = button_background(all[selected.0], *button);
+    }
+}
+
+fn button_background(selected: PauseButton, button: PauseButton) -> Color {
+    if selected == button {
+        BUTTON_SELECTED_BACKGROUND_COLOR
+    } else {
+        BUTTON_BACKGROUND_COLOR
+    }
+}
+
+fn setup_pause_menu(
+    mut commands: Commands,
+    mut selected: ResMut<SelectedPauseButton>,
+    asset_server: Res<AssetServer>,
+    network: Option<Res<NetworkGame>>,
+) {
+    selected.0 = 0;
+
+    let all = PauseButton::all(network.is_some());
+
+    let button_node = Node {
+        width: Val::Px(340.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(15.0)),
+        padding: UiRect::all(Val::Px(45.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(BACKGROUND_COLOR),
+            OnPauseScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Paused"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(96.0),
+                            ..default()
+                        },
+                        TextColor(TITLE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(40.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for &button in all {
+                        parent
+                            .spawn((
+                                Button,
+                                button_node.clone(),
+                                BackgroundColor(button_background(all[selected.0], button)),
+                                button,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(button.label()),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(48.0),
+                                        ..default()
+                                    },
+                                    TextColor(BUTTON_TEXT_COLOR),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::state::app::StatesPlugin;
+
+    use super::*;
+
+    #[test]
+    fn confirming_restart_with_the_pause_key_leaves_the_game() {
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin)
+            .insert_state(GameState::Game)
+            .add_sub_state::<PauseState>()
+            .init_state::<SettingsScreen>()
+            .init_resource::<ButtonInput<Action>>()
+            .init_resource::<SelectedPauseButton>()
+            .add_systems(Update, navigate.run_if(in_state(PauseState::Paused)));
+
+        app.world_mut()
+            .resource_mut::<NextState<PauseState>>()
+            .set(PauseState::Paused);
+        app.update();
+
+        // Space is bound to both actions by default
+        app.world_mut().resource_mut::<SelectedPauseButton>().0 = 1;
+        let mut actions = app.world_mut().resource_mut::<ButtonInput<Action>>();
+        actions.press(Action::Confirm);
+        actions.press(Action::Pause);
+        app.update();
+        app.update();
+
+        assert_eq!(
+            *app.world().resource::<State<GameState>>().get(),
+            GameState::Loading
+        );
+    }
+}
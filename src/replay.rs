@@ -0,0 +1,100 @@
+use anyhow::Result;
+use bevy::prelude::*;
+use std::path::Path;
+
+use crate::GameState;
+use crate::editor::TestPlay;
+use crate::game::CurrentGame;
+use crate::highscore::Leaderboard;
+use crate::save::{PERSISTENT, SaveFailed, data_dir, write_atomically};
+use crate::simulation::replay::Replay;
+
+const LAST_REPLAY: &str = "last.replay";
+const HIGHSCORE_REPLAY: &str = "highscore.replay";
+
+/// Saves the input of every game as replay and plays back a replay instead of the input of the
+/// player if a [`Playback`] resource exists.
+pub struct ReplayPlugin;
+
+/// A replay that is played back during the next game.
+#[derive(Resource)]
+pub struct Playback(pub Replay);
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnExit(GameState::Game), stop_playback);
+
+        if PERSISTENT {
+            app.add_systems(
+                OnExit(GameState::Game),
+                // the level that is tested in the editor may change or never be saved, so a
+                // replay of it couldn't be played back
+                save_replay
+                    .run_if(not(resource_exists::<Playback>))
+                    .run_if(not(resource_exists::<TestPlay>))
+                    .before(stop_playback),
+            )
+            .add_systems(
+                Update,
+                save_highscore_replay.run_if(resource_changed::<Leaderboard>),
+            );
+        }
+    }
+}
+
+/// Reads a replay from the given file.
+pub fn read_replay_from_file(path: &Path) -> Result<Replay> {
+    std::fs::read_to_string(path)?.parse()
+}
+
+/// Save the replay of the last game to the platform specific data directory (e.g.
+/// `$HOME/.local/share/diplopod/last.replay`).
+fn save_replay(game: Res<CurrentGame>, mut save_failed_writer: MessageWriter<SaveFailed>) {
+    if let Err(e) = write_replay_to_file(game.replay(), LAST_REPLAY) {
+        save_failed_writer.write(SaveFailed(format!("{:#}", e)));
+    }
+}
+
+/// Saves the replay of the last game as `highscore.replay` once it was added to the top of the
+/// leaderboard. Games that never get onto the leaderboard, like the ones of the campaign, are
+/// left out this way.
+fn save_highscore_replay(
+    game: Option<Res<CurrentGame>>,
+    leaderboard: Res<Leaderboard>,
+    mut save_failed_writer: MessageWriter<SaveFailed>,
+) {
+    let (Some(game), Some(entry)) = (
+        game,
+        leaderboard
+            .last_entry
+            .and_then(|index| leaderboard.entries.get(index)),
+    ) else {
+        return;
+    };
+
+    if entry.length < leaderboard.best(entry.rules, entry.level.as_deref())
+        || entry.seed != game.seed()
+        || entry.rules != game.rules()
+        || entry.level.as_deref() != game.replay().level()
+    {
+        return;
+    }
+
+    if let Err(e) = write_replay_to_file(game.replay(), HIGHSCORE_REPLAY) {
+        save_failed_writer.write(SaveFailed(format!("{:#}", e)));
+    }
+}
+
+/// Writes a replay to the platform specific data directory. The file and all necessary
+/// directories are created by the function.
+fn write_replay_to_file(replay: &Replay, file: &str) -> Result<()> {
+    let data_dir = data_dir()?;
+    std::fs::create_dir_all(&data_dir)?;
+
+    write_atomically(&data_dir.join(file), &replay.to_string())
+}
+
+/// Returns to the input of the player once the replay has been played back.
+fn stop_playback(mut commands: Commands) {
+    commands.remove_resource::<Playback>();
+}
@@ -0,0 +1,161 @@
+use bevy::color::palettes::css::ANTIQUE_WHITE;
+use bevy::prelude::*;
+
+use crate::controls::Action;
+use crate::game::CurrentGame;
+use crate::highscore::InitialDelay;
+use crate::network::Connection;
+use crate::settings::Settings;
+use crate::simulation::Mode;
+
+use super::{GameState, despawn_screen};
+
+/// Adds a screen that names the winner of a game with several players, or their shared score if
+/// they played together.
+pub struct ResultsPlugin;
+
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+
+#[derive(Component)]
+struct OnResultsScreen;
+
+impl Plugin for ResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Results), setup_results)
+            .add_systems(
+                Update,
+                navigate
+                    .run_if(in_state(GameState::Results))
+                    .run_if(not(resource_exists::<InitialDelay>)),
+            )
+            .add_systems(
+                OnExit(GameState::Results),
+                despawn_screen::<OnResultsScreen>,
+            );
+    }
+}
+
+/// Starts a rematch or returns to the menu. A rematch over the network has to wait for the other
+/// players.
+fn navigate(
+    actions: Res<ButtonInput<Action>>,
+    connection: Option<Res<Connection>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if actions.just_pressed(Action::Confirm) {
+        game_state.set(if connection.is_some() {
+            GameState::Connecting
+        } else {
+            GameState::Loading
+        });
+    }
+
+    if actions.just_pressed(Action::Back) {
+        game_state.set(GameState::Menu);
+    }
+}
+
+fn setup_results(
+    mut commands: Commands,
+    game: Res<CurrentGame>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+) {
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+    let palette = settings.theme.palette();
+    let colors = [palette.diplopod, palette.diplopod_second];
+
+    let mode = game.rules().mode;
+
+    let headline = match game.winner() {
+        _ if mode == Mode::Coop => format!("Together {}", game.length()),
+        Some(0) if mode == Mode::Computer => "You win".to_string(),
+        Some(winner) => format!("{} wins", player_name(mode, winner)),
+        None => "Draw".to_string(),
+    };
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            OnResultsScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new(headline),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(96.0),
+                            ..default()
+                        },
+                        TextColor(HEADLINE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(30.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for (player, diplopod) in game.diplopods().iter().enumerate() {
+                        parent.spawn((
+                            Text::new(format!(
+                                "{}    Length {}",
+                                player_name(mode, player),
+                                diplopod.segments().len()
+                            )),
+                            TextFont {
+                                font: font.clone(),
+                                font_size: FontSize::Px(40.0),
+                                ..default()
+                            },
+                            TextColor(colors[player % colors.len()]),
+                            Node {
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            },
+                        ));
+                    }
+
+                    parent.spawn((
+                        Text::new(format!(
+                            "Rematch with {}, go back with {}",
+                            settings.bindings.hint(Action::Confirm),
+                            settings.bindings.hint(Action::Back)
+                        )),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(24.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(30.0)),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+
+    commands.init_resource::<InitialDelay>();
+}
+
+/// The name of a player, which is a number unless the other player is the computer.
+fn player_name(mode: Mode, player: usize) -> String {
+    match (mode, player) {
+        (Mode::Computer, 0) => "You".to_string(),
+        (Mode::Computer, _) => "Computer".to_string(),
+        _ => format!("Player {}", player + 1),
+    }
+}
@@ -0,0 +1,570 @@
+use anyhow::{Context, Result, anyhow};
+use bevy::prelude::*;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::TITLE;
+use crate::campaign::CampaignProgress;
+use crate::highscore::{Entry, Leaderboard, now};
+use crate::settings::Settings;
+use crate::simulation::Rules;
+
+const QUALIFIER: &str = "com.github";
+const ORGANIZATION: &str = "tehlers";
+
+const SAVE: &str = "save.ron";
+const LEGACY_HIGHSCORE: &str = "highscore";
+
+/// Version of the format of the save file. It has to be increased whenever the format changes
+/// in an incompatible way and `parse_save` has to migrate the older versions.
+const VERSION: u32 = 1;
+
+/// Number of save files of earlier sessions that are kept as `save.ron.1`, `save.ron.2` etc.
+const BACKUPS: u32 = 3;
+
+/// Data is only persisted where there is a file system. In the browser every session starts
+/// with the defaults, which is not reported as a failure.
+pub const PERSISTENT: bool = !cfg!(target_arch = "wasm32");
+
+const NOTICE_COLOR: Color = Color::srgb(1.0, 0.3, 0.3);
+const NOTICE_SECONDS: f32 = 5.0;
+
+/// Loads the leaderboard, statistics, settings and campaign progress from a single save file in
+/// the platform specific data directory and writes them back whenever they change. Failures are
+/// shown to the player.
+pub struct SavePlugin;
+
+/// Reports that persisted data could not be read or written.
+#[derive(Message)]
+pub struct SaveFailed(pub String);
+
+/// A message about a failure to read or write persisted data that is shown for a few seconds.
+#[derive(Component)]
+struct SaveFailedNotice {
+    timer: Timer,
+}
+
+/// Stacks the notices about failures at the bottom of the screen, so that several of them can
+/// be read at the same time.
+#[derive(Component)]
+struct SaveFailedNotices;
+
+/// Everything that is persisted between sessions.
+#[derive(Serialize, Deserialize)]
+struct SaveData {
+    version: u32,
+    #[serde(default)]
+    leaderboard: Vec<Entry>,
+    #[serde(default)]
+    statistics: Statistics,
+    #[serde(default)]
+    settings: Settings,
+    #[serde(default)]
+    campaign: CampaignProgress,
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            leaderboard: Vec::new(),
+            statistics: Statistics::default(),
+            settings: Settings::default(),
+            campaign: CampaignProgress::default(),
+        }
+    }
+}
+
+/// Only the version of a save file, used to decide how the rest of it is parsed.
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+/// Totals of all games that were played.
+#[derive(Clone, Default, Resource, Serialize, Deserialize)]
+pub struct Statistics {
+    pub games_played: u32,
+    pub total_length: u64,
+    pub play_time: Duration,
+}
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let mut failures = Vec::new();
+        let save = match data_dir() {
+            Ok(data_dir) if PERSISTENT => load_save(&data_dir, &mut failures),
+            Err(e) if PERSISTENT => {
+                failures.push(format!("{} Save file can't be read.", e));
+                SaveData::default()
+            }
+            _ => SaveData::default(),
+        };
+
+        if PERSISTENT {
+            app.add_systems(
+                Update,
+                save_data.run_if(
+                    resource_changed::<Leaderboard>
+                        .or_else(resource_changed::<Statistics>)
+                        .or_else(resource_changed::<Settings>)
+                        .or_else(resource_changed::<CampaignProgress>)
+                        .and_then(not(resource_added::<Statistics>)),
+                ),
+            );
+        }
+
+        app.add_systems(
+            Update,
+            (
+                show_save_failed.run_if(on_message::<SaveFailed>),
+                hide_save_failed,
+            ),
+        )
+        .insert_resource(Leaderboard {
+            entries: save.leaderboard,
+            last_entry: None,
+        })
+        .insert_resource(save.statistics)
+        .insert_resource(save.settings)
+        .insert_resource(save.campaign)
+        .add_message::<SaveFailed>();
+
+        for failure in failures {
+            app.world_mut().write_message(SaveFailed(failure));
+        }
+    }
+}
+
+/// Returns the platform specific data directory (e.g. `$HOME/.local/share/diplopod`).
+pub fn data_dir() -> Result<PathBuf> {
+    ProjectDirs::from(QUALIFIER, ORGANIZATION, TITLE)
+        .map(|project_dirs| project_dirs.data_dir().to_path_buf())
+        .ok_or_else(|| anyhow!("Unable to retrieve path to data directory."))
+}
+
+/// Loads the save file from the given data directory. Data of earlier versions without a save
+/// file is migrated. If the save file is invalid it is moved aside and the most recent valid
+/// backup is used instead. Failures are added to `failures`.
+fn load_save(data_dir: &Path, failures: &mut Vec<String>) -> SaveData {
+    let path = data_dir.join(SAVE);
+
+    if !path.exists() {
+        let save = migrate_legacy_files(data_dir, failures);
+
+        if let Err(e) = write_save_to_file(data_dir, &save, false) {
+            failures.push(format!("{:#}", e));
+        }
+
+        return save;
+    }
+
+    match read_save_from_file(&path) {
+        Ok(save) => return save,
+        Err(e) => failures.push(format!("{:#}", e)),
+    }
+
+    match move_aside(&path) {
+        Ok(moved) => failures.push(format!(
+            "Invalid save file was moved to {}",
+            moved.display()
+        )),
+        Err(e) => failures.push(format!("{:#}", e)),
+    }
+
+    for index in 1..=BACKUPS {
+        if let Ok(save) = read_save_from_file(&backup_path(&path, index)) {
+            failures.push(format!("Restored backup {} of the save file", index));
+            return save;
+        }
+    }
+
+    SaveData::default()
+}
+
+fn read_save_from_file(path: &Path) -> Result<SaveData> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read save file {}", path.display()))?;
+
+    parse_save(&contents).with_context(|| format!("Invalid save file {}", path.display()))
+}
+
+/// Parses the contents of a save file of any known version.
+fn parse_save(contents: &str) -> Result<SaveData> {
+    let SaveVersion { version } = ron::from_str(contents)?;
+
+    match version {
+        VERSION => Ok(ron::from_str(contents)?),
+        _ => Err(anyhow!("Unsupported version {}", version)),
+    }
+}
+
+/// Moves an invalid file aside, so that it is neither used nor overwritten.
+fn move_aside(path: &Path) -> Result<PathBuf> {
+    let moved = path.with_extension(format!("{}.invalid", now()));
+
+    std::fs::rename(path, &moved)
+        .with_context(|| format!("Unable to move {} aside", path.display()))?;
+
+    Ok(moved)
+}
+
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}", index));
+    backup.into()
+}
+
+/// Keeps a copy of the current file as first backup and shifts older backups. The oldest backup
+/// is dropped.
+fn rotate_backups(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for index in (1..BACKUPS).rev() {
+        let backup = backup_path(path, index);
+        if backup.exists() {
+            std::fs::rename(&backup, backup_path(path, index + 1))?;
+        }
+    }
+
+    std::fs::copy(path, backup_path(path, 1))?;
+
+    Ok(())
+}
+
+/// Replaces the contents of a file without ever leaving a partially written file behind: the
+/// contents are written to a temporary file first, which is renamed to the final name once it
+/// has been completely written. The temporary file is removed if it can't be written, e.g. on a
+/// full disk.
+pub fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = File::create(&temporary)
+        .with_context(|| format!("Unable to create {}", temporary.display()))?;
+
+    if let Err(e) = file
+        .write_all(contents.as_bytes())
+        .and_then(|_| file.sync_all())
+    {
+        drop(file);
+        let _ = std::fs::remove_file(&temporary);
+
+        return Err(e).with_context(|| format!("Unable to write {}", temporary.display()));
+    }
+
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Unable to replace {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Takes over the single highscore that was stored by earlier versions.
+fn migrate_legacy_files(data_dir: &Path, failures: &mut Vec<String>) -> SaveData {
+    let path = data_dir.join(LEGACY_HIGHSCORE);
+
+    let leaderboard = if path.exists() {
+        read_legacy_highscore(&path)
+    } else {
+        Ok(Vec::new())
+    };
+
+    match leaderboard {
+        Ok(leaderboard) => SaveData {
+            leaderboard,
+            ..default()
+        },
+        Err(e) => {
+            failures.push(format!("Unable to migrate earlier highscore: {}", e));
+            SaveData::default()
+        }
+    }
+}
+
+/// Reads a single highscore stored as plain number. An empty file or a highscore of `0` means
+/// that no game was finished yet.
+fn read_legacy_highscore(path: &Path) -> Result<Vec<Entry>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    if contents.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let length: u16 = contents.trim().parse()?;
+
+    if length == 0 {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![Entry {
+        name: "---".to_string(),
+        date: 0,
+        length,
+        duration: Duration::ZERO,
+        seed: 0,
+        rules: Rules::default(),
+        level: None,
+    }])
+}
+
+/// Saves all persisted data.
+fn save_data(
+    leaderboard: Res<Leaderboard>,
+    statistics: Res<Statistics>,
+    settings: Res<Settings>,
+    campaign: Res<CampaignProgress>,
+    mut save_failed_writer: MessageWriter<SaveFailed>,
+    mut backed_up: Local<bool>,
+) {
+    let save = SaveData {
+        version: VERSION,
+        leaderboard: leaderboard.entries.clone(),
+        statistics: statistics.clone(),
+        settings: settings.clone(),
+        campaign: campaign.clone(),
+    };
+
+    // the backups keep the save files of earlier sessions, the settings alone may change many
+    // times during a session
+    match data_dir().and_then(|data_dir| write_save_to_file(&data_dir, &save, !*backed_up)) {
+        Ok(()) => *backed_up = true,
+        Err(e) => {
+            save_failed_writer.write(SaveFailed(format!("{:#}", e)));
+        }
+    }
+}
+
+/// Writes the save file to the given data directory, optionally after keeping a backup of the
+/// previous version. All necessary directories are created by the function.
+fn write_save_to_file(data_dir: &Path, save: &SaveData, backup: bool) -> Result<()> {
+    std::fs::create_dir_all(data_dir)?;
+
+    let path = data_dir.join(SAVE);
+    let contents = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+
+    if backup {
+        rotate_backups(&path).context("Unable to back up save file")?;
+    }
+
+    write_atomically(&path, &contents)
+}
+
+/// Shows failures to read or write persisted data at the bottom of the screen. They are logged
+/// as well.
+fn show_save_failed(
+    mut commands: Commands,
+    mut reader: MessageReader<SaveFailed>,
+    notices: Option<Single<Entity, With<SaveFailedNotices>>>,
+    asset_server: Res<AssetServer>,
+) {
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    let notices = match notices {
+        Some(notices) => *notices,
+        None => commands
+            .spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                SaveFailedNotices,
+            ))
+            .id(),
+    };
+
+    for SaveFailed(failure) in reader.read() {
+        warn!("{}", failure);
+
+        commands.spawn((
+            Text::new(failure.clone()),
+            TextFont {
+                font: font.clone(),
+                font_size: FontSize::Px(20.0),
+                ..default()
+            },
+            TextColor(NOTICE_COLOR),
+            ChildOf(notices),
+            SaveFailedNotice {
+                timer: Timer::from_seconds(NOTICE_SECONDS, TimerMode::Once),
+            },
+        ));
+    }
+}
+
+fn hide_save_failed(
+    mut commands: Commands,
+    mut notices: Query<(Entity, &mut SaveFailedNotice)>,
+    time: Res<Time<Real>>,
+) {
+    for (entity, mut notice) in notices.iter_mut() {
+        notice.timer.tick(time.delta());
+
+        if notice.timer.is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for the files of a single test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("diplopod-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn migrate(name: &str, highscore: &str) -> (SaveData, Vec<String>) {
+        let dir = test_dir(name);
+        std::fs::write(dir.join(LEGACY_HIGHSCORE), highscore).unwrap();
+
+        let mut failures = Vec::new();
+        let save = load_save(&dir, &mut failures);
+
+        assert!(dir.join(SAVE).exists());
+
+        (save, failures)
+    }
+
+    fn entry(length: u16) -> Entry {
+        Entry {
+            name: "ABC".to_string(),
+            date: 0,
+            length,
+            duration: Duration::from_secs(60),
+            seed: 1,
+            rules: Rules::default(),
+            level: None,
+        }
+    }
+
+    #[test]
+    fn legacy_highscore_becomes_an_entry() {
+        let (save, failures) = migrate("legacy-valid", "42\n");
+
+        assert!(failures.is_empty());
+        assert_eq!(save.leaderboard.len(), 1);
+        assert_eq!(save.leaderboard[0].length, 42);
+    }
+
+    #[test]
+    fn empty_or_zero_legacy_highscore_is_no_entry() {
+        for (name, highscore) in [("legacy-empty", ""), ("legacy-zero", "0")] {
+            let (save, failures) = migrate(name, highscore);
+
+            assert!(failures.is_empty());
+            assert!(save.leaderboard.is_empty());
+        }
+    }
+
+    #[test]
+    fn invalid_legacy_highscore_is_reported() {
+        let (save, failures) = migrate("legacy-garbage", "many");
+
+        assert_eq!(failures.len(), 1);
+        assert!(save.leaderboard.is_empty());
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        assert!(parse_save(&format!("(version: {})", VERSION + 1)).is_err());
+        assert!(parse_save(&format!("(version: {})", VERSION)).is_ok());
+    }
+
+    #[test]
+    fn backups_are_rotated_from_newest_to_oldest() {
+        let dir = test_dir("rotate-backups");
+        let path = dir.join(SAVE);
+
+        for version in 1..=BACKUPS + 2 {
+            rotate_backups(&path).unwrap();
+            write_atomically(&path, &version.to_string()).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+
+        assert_eq!(read(&path), "5");
+        assert_eq!(read(&backup_path(&path, 1)), "4");
+        assert_eq!(read(&backup_path(&path, 2)), "3");
+        assert_eq!(read(&backup_path(&path, 3)), "2");
+        assert!(!backup_path(&path, BACKUPS + 1).exists());
+    }
+
+    #[test]
+    fn file_is_only_replaced_once_completely_written() {
+        let dir = test_dir("write-atomically");
+        let path = dir.join(SAVE);
+
+        write_atomically(&path, "old").unwrap();
+        assert!(!dir.join("save.ron.tmp").exists());
+
+        // the temporary file can't be created where a directory is in the way
+        std::fs::create_dir(dir.join("save.ron.tmp")).unwrap();
+
+        assert!(write_atomically(&path, "new").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_write_leaves_no_temporary_file() {
+        let dir = test_dir("full-disk");
+        let path = dir.join(SAVE);
+        let temporary = dir.join("save.ron.tmp");
+
+        write_atomically(&path, "old").unwrap();
+
+        // every write to /dev/full fails as if the disk was full
+        std::os::unix::fs::symlink("/dev/full", &temporary).unwrap();
+
+        assert!(write_atomically(&path, "new").is_err());
+        assert!(std::fs::symlink_metadata(&temporary).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+    }
+
+    #[test]
+    fn invalid_save_is_moved_aside_and_backup_restored() {
+        let dir = test_dir("restore-backup");
+        let path = dir.join(SAVE);
+
+        let backup = SaveData {
+            leaderboard: vec![entry(12)],
+            ..default()
+        };
+        write_save_to_file(&dir, &backup, false).unwrap();
+        std::fs::rename(&path, backup_path(&path, 1)).unwrap();
+        std::fs::write(&path, "garbage").unwrap();
+
+        let mut failures = Vec::new();
+        let save = load_save(&dir, &mut failures);
+
+        assert_eq!(save.leaderboard, vec![entry(12)]);
+        assert!(failures.contains(&"Restored backup 1 of the save file".to_string()));
+        assert!(!path.exists());
+
+        let moved: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".invalid"))
+            .collect();
+
+        assert_eq!(moved.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.join(&moved[0])).unwrap(),
+            "garbage"
+        );
+    }
+}
@@ -0,0 +1,604 @@
+use bevy::{
+    audio::Volume,
+    color::palettes::css::{ANTIQUE_WHITE, BLUE, ORANGE, RED},
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::controls::{Action, Bindings};
+use crate::game::{DiplopodColors, SelectedMode};
+use crate::simulation::computer::Difficulty;
+use crate::simulation::{ArenaSize, Mode, Movement, Progression};
+
+use super::despawn_screen;
+
+/// Adds a screen to change the settings and applies them whenever they change. The settings are
+/// persisted together with the leaderboard.
+pub struct SettingsPlugin;
+
+/// The settings and controls screens are shown on top of the menu or the paused game, which stay
+/// in their state meanwhile.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+pub enum SettingsScreen {
+    #[default]
+    Closed,
+    Settings,
+    Controls,
+}
+
+const HEADLINE_COLOR: Color = Color::Srgba(ANTIQUE_WHITE);
+const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+const VALUE_COLOR: Color = Color::WHITE;
+const ROW_BACKGROUND_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const ROW_SELECTED_BACKGROUND_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+
+/// Highest volume, every step is a tenth of the full volume.
+const MAX_VOLUME: u8 = 10;
+
+/// Smallest and largest dead zone of the gamepad axes in percent.
+const DEAD_ZONE_RANGE: (u8, u8) = (10, 90);
+const DEAD_ZONE_STEP: u8 = 10;
+
+#[derive(Component)]
+struct OnSettingsScreen;
+
+/// Everything the player can configure.
+#[derive(Clone, Debug, PartialEq, Eq, Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub progression: Progression,
+    pub movement: Movement,
+    pub reverse_protection: ReverseProtection,
+    pub arena: ArenaSize,
+    pub wrap_around: bool,
+    /// How well the computer plays against the player.
+    pub difficulty: Difficulty,
+    /// Volume from `0` (muted) to [`MAX_VOLUME`].
+    pub volume: u8,
+    pub fullscreen: bool,
+    pub rumble: bool,
+    /// How far the sticks have to be tilted in percent, before they count as pressed.
+    pub dead_zone: u8,
+    pub theme: Theme,
+    pub bindings: Bindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            progression: Progression::Classic,
+            movement: Movement::EightWay,
+            reverse_protection: ReverseProtection::default(),
+            arena: ArenaSize::Medium,
+            wrap_around: false,
+            difficulty: Difficulty::Normal,
+            volume: MAX_VOLUME,
+            fullscreen: false,
+            rumble: true,
+            dead_zone: 50,
+            theme: Theme::Classic,
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+/// Whether directions that would turn the head straight back into its neck are ignored,
+/// separately for every mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredReverseProtection")]
+pub struct ReverseProtection {
+    pub single: bool,
+    pub versus: bool,
+    pub coop: bool,
+    pub computer: bool,
+}
+
+impl ReverseProtection {
+    pub fn get(&self, mode: Mode) -> bool {
+        match mode {
+            Mode::Single => self.single,
+            Mode::Versus => self.versus,
+            Mode::Coop => self.coop,
+            Mode::Computer => self.computer,
+        }
+    }
+
+    fn toggle(&mut self, mode: Mode) {
+        let protection = match mode {
+            Mode::Single => &mut self.single,
+            Mode::Versus => &mut self.versus,
+            Mode::Coop => &mut self.coop,
+            Mode::Computer => &mut self.computer,
+        };
+
+        *protection = !*protection;
+    }
+}
+
+/// Earlier versions stored a single reverse protection for all modes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredReverseProtection {
+    All(bool),
+    PerMode {
+        #[serde(default)]
+        single: bool,
+        #[serde(default)]
+        versus: bool,
+        #[serde(default)]
+        coop: bool,
+        #[serde(default)]
+        computer: bool,
+    },
+}
+
+impl From<StoredReverseProtection> for ReverseProtection {
+    fn from(stored: StoredReverseProtection) -> Self {
+        match stored {
+            StoredReverseProtection::All(all) => Self {
+                single: all,
+                versus: all,
+                coop: all,
+                computer: all,
+            },
+            StoredReverseProtection::PerMode {
+                single,
+                versus,
+                coop,
+                computer,
+            } => Self {
+                single,
+                versus,
+                coop,
+                computer,
+            },
+        }
+    }
+}
+
+/// The colors of the diplopod, the consumables and the walls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    Classic,
+    Pastel,
+    /// Colors that can be told apart with the common forms of color blindness.
+    Colorblind,
+}
+
+impl Theme {
+    const ALL: [Self; 3] = [Self::Classic, Self::Pastel, Self::Colorblind];
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Classic => Palette {
+                antidote: Color::WHITE,
+                diplopod: Color::Srgba(ORANGE),
+                diplopod_immune: Color::WHITE,
+                diplopod_second: Color::srgb(0.7, 0.3, 1.0),
+                food: Color::srgb(0.0, 1.0, 0.0),
+                poison_fill: Color::BLACK,
+                poison_outline: Color::Srgba(RED),
+                superfood: Color::Srgba(BLUE),
+                wall: Color::srgb(0.25, 0.25, 0.25),
+            },
+            Theme::Pastel => Palette {
+                antidote: Color::WHITE,
+                diplopod: Color::srgb(1.0, 0.75, 0.5),
+                diplopod_immune: Color::WHITE,
+                diplopod_second: Color::srgb(0.75, 0.6, 1.0),
+                food: Color::srgb(0.6, 0.9, 0.6),
+                poison_fill: Color::BLACK,
+                poison_outline: Color::srgb(0.95, 0.5, 0.55),
+                superfood: Color::srgb(0.55, 0.65, 1.0),
+                wall: Color::srgb(0.3, 0.3, 0.35),
+            },
+            Theme::Colorblind => Palette {
+                antidote: Color::WHITE,
+                diplopod: Color::srgb(0.9, 0.6, 0.0),
+                diplopod_immune: Color::WHITE,
+                diplopod_second: Color::srgb(0.0, 0.6, 0.5),
+                food: Color::srgb(0.35, 0.7, 0.9),
+                poison_fill: Color::BLACK,
+                poison_outline: Color::srgb(0.8, 0.4, 0.7),
+                superfood: Color::srgb(0.95, 0.9, 0.25),
+                wall: Color::srgb(0.25, 0.25, 0.25),
+            },
+        }
+    }
+}
+
+/// The colors of a [`Theme`].
+pub struct Palette {
+    pub antidote: Color,
+    pub diplopod: Color,
+    pub diplopod_immune: Color,
+    /// The diplopod of the second player.
+    pub diplopod_second: Color,
+    pub food: Color,
+    pub poison_fill: Color,
+    pub poison_outline: Color,
+    pub superfood: Color,
+    pub wall: Color,
+}
+
+/// A line of the settings screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Setting {
+    Speed,
+    Movement,
+    ReverseProtection,
+    Arena,
+    WrapAround,
+    Difficulty,
+    Volume,
+    Fullscreen,
+    Rumble,
+    DeadZone,
+    Theme,
+    Controls,
+}
+
+impl Setting {
+    const ALL: [Self; 12] = [
+        Self::Speed,
+        Self::Movement,
+        Self::ReverseProtection,
+        Self::Arena,
+        Self::WrapAround,
+        Self::Difficulty,
+        Self::Volume,
+        Self::Fullscreen,
+        Self::Rumble,
+        Self::DeadZone,
+        Self::Theme,
+        Self::Controls,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Setting::Speed => "Speed",
+            Setting::Movement => "Movement",
+            Setting::ReverseProtection => "Reverse protection",
+            Setting::Arena => "Arena",
+            Setting::WrapAround => "Wrap around",
+            Setting::Difficulty => "Computer",
+            Setting::Volume => "Volume",
+            Setting::Fullscreen => "Fullscreen",
+            Setting::Rumble => "Rumble",
+            Setting::DeadZone => "Dead zone",
+            Setting::Theme => "Colors",
+            Setting::Controls => "Controls",
+        }
+    }
+}
+
+/// Index of the selected setting.
+#[derive(Default, Resource)]
+struct SelectedSetting(usize);
+
+/// Displays the value of a setting.
+#[derive(Component)]
+struct Value(Setting);
+
+impl Settings {
+    /// The dead zone of the gamepad axes as fraction of the full tilt.
+    pub fn dead_zone(&self) -> f32 {
+        f32::from(self.dead_zone) / 100.0
+    }
+
+    /// The value of a setting. The reverse protection is shown for the mode selected in the
+    /// menu.
+    fn value(&self, option: Setting, mode: Mode) -> String {
+        match option {
+            Setting::Speed => format!("{:?}", self.progression),
+            Setting::Movement => match self.movement {
+                Movement::EightWay => "8-way".to_string(),
+                Movement::FourWay => "4-way".to_string(),
+                Movement::Turns => "Turn left/right".to_string(),
+            },
+            Setting::Volume => format!("{}%", u32::from(self.volume) * 100 / MAX_VOLUME as u32),
+            Setting::ReverseProtection => format!(
+                "{} ({})",
+                on_off(self.reverse_protection.get(mode)),
+                mode_name(mode)
+            ),
+            Setting::Arena => format!("{:?}", self.arena),
+            Setting::WrapAround => on_off(self.wrap_around),
+            Setting::Difficulty => format!("{:?}", self.difficulty),
+            Setting::Fullscreen => on_off(self.fullscreen),
+            Setting::Rumble => on_off(self.rumble),
+            Setting::DeadZone => format!("{}%", self.dead_zone),
+            Setting::Theme => format!("{:?}", self.theme),
+            Setting::Controls => "...".to_string(),
+        }
+    }
+
+    /// Changes a setting to the next (`delta` > 0) or previous (`delta` < 0) value. The reverse
+    /// protection is changed for the mode selected in the menu.
+    fn change(&mut self, option: Setting, delta: i32, mode: Mode) {
+        match option {
+            Setting::Speed => self.progression = cycle(&Progression::ALL, self.progression, delta),
+            Setting::Arena => self.arena = cycle(&ArenaSize::ALL, self.arena, delta),
+            Setting::Movement => {
+                self.movement = cycle(
+                    &[Movement::EightWay, Movement::FourWay, Movement::Turns],
+                    self.movement,
+                    delta,
+                )
+            }
+            Setting::Volume => {
+                self.volume = (i32::from(self.volume) + delta).clamp(0, MAX_VOLUME.into()) as u8
+            }
+            Setting::ReverseProtection => self.reverse_protection.toggle(mode),
+            Setting::WrapAround => self.wrap_around = !self.wrap_around,
+            Setting::Difficulty => {
+                self.difficulty = cycle(&Difficulty::ALL, self.difficulty, delta)
+            }
+            Setting::Fullscreen => self.fullscreen = !self.fullscreen,
+            Setting::Rumble => self.rumble = !self.rumble,
+            Setting::DeadZone => {
+                self.dead_zone = (i32::from(self.dead_zone) + delta * i32::from(DEAD_ZONE_STEP))
+                    .clamp(DEAD_ZONE_RANGE.0.into(), DEAD_ZONE_RANGE.1.into())
+                    as u8
+            }
+            Setting::Theme => self.theme = cycle(&Theme::ALL, self.theme, delta),
+            // edited on a screen of its own
+            Setting::Controls => (),
+        }
+    }
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Single => "Single",
+        Mode::Versus => "Versus",
+        Mode::Coop => "Co-op",
+        Mode::Computer => "Computer",
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "On" } else { "Off" }.to_string()
+}
+
+/// Returns the value `delta` steps away from `current`, wrapping around at both ends.
+fn cycle<T: Copy + PartialEq>(values: &[T], current: T, delta: i32) -> T {
+    let index = values
+        .iter()
+        .position(|&value| value == current)
+        .unwrap_or(0);
+    values[(index as i32 + delta).rem_euclid(values.len() as i32) as usize]
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(SettingsScreen::Settings), setup_settings)
+            .add_systems(
+                Update,
+                (
+                    (navigate, update_values)
+                        .chain()
+                        .run_if(in_state(SettingsScreen::Settings)),
+                    apply_settings.run_if(resource_changed::<Settings>),
+                ),
+            )
+            .add_systems(
+                OnExit(SettingsScreen::Settings),
+                despawn_screen::<OnSettingsScreen>,
+            )
+            .init_state::<SettingsScreen>()
+            .init_resource::<SelectedSetting>();
+    }
+}
+
+/// Applies the volume, window mode and colors whenever the settings change. The speed,
+/// rumble and bindings are read by the game and the controls.
+fn apply_settings(
+    settings: Res<Settings>,
+    mut global_volume: ResMut<GlobalVolume>,
+    mut sinks: Query<(&mut AudioSink, &PlaybackSettings)>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    diplopod_colors: Res<DiplopodColors>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    global_volume.volume = Volume::Linear(f32::from(settings.volume) / f32::from(MAX_VOLUME));
+
+    // the global volume only reaches sounds when they start, so sounds that already play, like
+    // the looped antidote sound, get the new volume here
+    for (mut sink, playback_settings) in sinks.iter_mut() {
+        sink.set_volume(playback_settings.volume * global_volume.volume);
+    }
+
+    window.mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+    } else {
+        WindowMode::Windowed
+    };
+
+    let palette = settings.theme.palette();
+
+    for (material, color) in [
+        (&diplopod_colors.antidote, palette.antidote),
+        (&diplopod_colors.diplopod_normal, palette.diplopod),
+        (&diplopod_colors.diplopod_immune, palette.diplopod_immune),
+        (&diplopod_colors.diplopod_second, palette.diplopod_second),
+        (&diplopod_colors.food, palette.food),
+        (&diplopod_colors.poison_fill, palette.poison_fill),
+        (&diplopod_colors.poison_outline, palette.poison_outline),
+        (&diplopod_colors.superfood, palette.superfood),
+        (&diplopod_colors.wall, palette.wall),
+    ] {
+        if let Some(mut material) = materials.get_mut(&material.0) {
+            material.color = color;
+        }
+    }
+}
+
+/// Selects a setting with up and down and changes it with left, right and confirm. The
+/// controls are edited on a screen of their own.
+fn navigate(
+    actions: Res<ButtonInput<Action>>,
+    mut selected: ResMut<SelectedSetting>,
+    mut settings: ResMut<Settings>,
+    mut settings_screen: ResMut<NextState<SettingsScreen>>,
+    mode: Res<SelectedMode>,
+) {
+    if actions.just_pressed(Action::Up) {
+        selected.0 = (selected.0 + Setting::ALL.len() - 1) % Setting::ALL.len();
+    }
+
+    if actions.just_pressed(Action::Down) {
+        selected.0 = (selected.0 + 1) % Setting::ALL.len();
+    }
+
+    let setting = Setting::ALL[selected.0];
+
+    if setting == Setting::Controls {
+        if actions.any_just_pressed([Action::Right, Action::Confirm]) {
+            settings_screen.set(SettingsScreen::Controls);
+        }
+    } else {
+        if actions.just_pressed(Action::Left) {
+            settings.change(setting, -1, mode.0);
+        }
+
+        if actions.any_just_pressed([Action::Right, Action::Confirm]) {
+            settings.change(setting, 1, mode.0);
+        }
+    }
+
+    if actions.just_pressed(Action::Back) {
+        settings_screen.set(SettingsScreen::Closed);
+    }
+}
+
+fn update_values(
+    settings: Res<Settings>,
+    selected: Res<SelectedSetting>,
+    mode: Res<SelectedMode>,
+    mut values: Query<(&Value, &mut Text, &ChildOf)>,
+    mut rows: Query<&mut BackgroundColor>,
+) {
+    if !settings.is_changed() && !selected.is_changed() {
+        return;
+    }
+
+    for (value, mut text, child_of) in values.iter_mut() {
+        text.0 = settings.value(value.0, mode.0);
+
+        if let Ok(mut background_color) = rows.get_mut(child_of.parent()) {
+            background_color.0 = row_background_color(&selected, value.0);
+        }
+    }
+}
+
+fn row_background_color(selected: &SelectedSetting, option: Setting) -> Color {
+    if Setting::ALL[selected.0] == option {
+        ROW_SELECTED_BACKGROUND_COLOR
+    } else {
+        ROW_BACKGROUND_COLOR
+    }
+}
+
+/// Creates the UI of the settings screen.
+fn setup_settings(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    selected: Res<SelectedSetting>,
+    mode: Res<SelectedMode>,
+    asset_server: Res<AssetServer>,
+) {
+    let font: FontSource = asset_server.load("fonts/AllertaStencil-Regular.ttf").into();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            // covers the menu or the paused game underneath
+            BackgroundColor(Color::BLACK),
+            GlobalZIndex(1),
+            OnSettingsScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Settings"),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(64.0),
+                            ..default()
+                        },
+                        TextColor(HEADLINE_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+
+                    for option in Setting::ALL {
+                        parent
+                            .spawn((
+                                Node {
+                                    width: Val::Px(640.0),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    padding: UiRect::horizontal(Val::Px(20.0)),
+                                    justify_content: JustifyContent::SpaceBetween,
+                                    ..default()
+                                },
+                                BackgroundColor(row_background_color(&selected, option)),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    Text::new(option.label()),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(32.0),
+                                        ..default()
+                                    },
+                                    TextColor(TEXT_COLOR),
+                                ));
+
+                                parent.spawn((
+                                    Text::new(settings.value(option, mode.0)),
+                                    TextFont {
+                                        font: font.clone(),
+                                        font_size: FontSize::Px(32.0),
+                                        ..default()
+                                    },
+                                    TextColor(VALUE_COLOR),
+                                    Value(option),
+                                ));
+                            });
+                    }
+
+                    parent.spawn((
+                        Text::new(format!(
+                            "Change with left and right, go back with {}",
+                            settings.bindings.hint(Action::Back)
+                        )),
+                        TextFont {
+                            font: font.clone(),
+                            font_size: FontSize::Px(24.0),
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                        Node {
+                            margin: UiRect::all(Val::Px(25.0)),
+                            ..default()
+                        },
+                    ));
+                });
+        });
+}
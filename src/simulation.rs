@@ -0,0 +1,1196 @@
+//! Engine independent implementation of the rules of diplopod.
+//!
+//! A [`Simulation`] holds the complete state of a running game and is advanced one tick at a
+//! time by [`Simulation::tick`]. It does not depend on Bevy, so it can be driven without a window
+//! and is only rendered by the `game` module.
+//!
+//! All randomness is drawn from a generator seeded at creation, so the same seed and the same
+//! inputs always result in the same game.
+
+pub mod computer;
+pub mod diplopod;
+pub mod level;
+pub mod replay;
+
+use diplopod::Diplopod;
+use level::{Goal, Level};
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use replay::Replay;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Time between two ticks of the simulation at the start of a classic game.
+pub const TICK_DURATION: Duration = Duration::from_millis(75);
+
+pub const CONSUMABLE_SCALE_FACTOR: i32 = 2;
+
+/// Number of ticks an antidote protects against poison (roughly ten seconds).
+pub const IMMUNITY_TICKS: u32 = 133;
+
+/// Maximum number of diplopods in a game.
+pub const MAX_PLAYERS: usize = 2;
+
+/// Combined length that players who work together have to reach on a level without a goal.
+pub const COOP_GOAL_LENGTH: usize = 100;
+
+const ANTIDOTE_MOVE_INTERVAL: u64 = 7;
+
+/// Position of a wall or a consumable.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Position of a diplopod segment. The grid of tiles is finer than the grid of positions: every
+/// position covers `CONSUMABLE_SCALE_FACTOR` × `CONSUMABLE_SCALE_FACTOR` tiles.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Tile {
+    pub fn step(self, direction: Direction) -> Self {
+        Tile {
+            x: self.x + direction.x,
+            y: self.y + direction.y,
+        }
+    }
+}
+
+impl From<Tile> for Position {
+    fn from(tile: Tile) -> Self {
+        Position {
+            x: tile.x.div_euclid(CONSUMABLE_SCALE_FACTOR),
+            y: tile.y.div_euclid(CONSUMABLE_SCALE_FACTOR),
+        }
+    }
+}
+
+/// Direction of movement. Both components are in the range `-1..=1`, so diagonal movement is
+/// possible.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Direction {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Direction {
+    pub const NONE: Direction = Direction { x: 0, y: 0 };
+
+    pub fn new(x: i32, y: i32) -> Self {
+        Direction {
+            x: x.signum(),
+            y: y.signum(),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Direction::NONE
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        self.x != 0 && self.y != 0
+    }
+
+    pub fn reversed(&self) -> Self {
+        Direction {
+            x: -self.x,
+            y: -self.y,
+        }
+    }
+}
+
+/// How the diplopod can be steered.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Movement {
+    /// Horizontally, vertically and diagonally like in the original game.
+    #[default]
+    EightWay,
+    /// Only horizontally and vertically. Reversing the direction is ignored.
+    FourWay,
+    /// Only by turning left or right relative to the current direction. The simulation treats
+    /// it like [`Movement::FourWay`], the turns are translated into directions by the input.
+    Turns,
+}
+
+/// How the time between two ticks shrinks while the diplopod grows.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Progression {
+    Relaxed,
+    #[default]
+    Classic,
+    Frantic,
+}
+
+impl Progression {
+    pub const ALL: [Self; 3] = [Self::Relaxed, Self::Classic, Self::Frantic];
+
+    /// Time between two ticks for a diplopod of the given length.
+    pub fn tick_duration(self, length: usize) -> Duration {
+        // start, minimum and number of segments that shorten the tick by a millisecond
+        let (start, minimum, segments_per_millisecond): (u64, u64, usize) = match self {
+            Progression::Relaxed => (100, 75, 4),
+            Progression::Classic => (75, 50, 4),
+            Progression::Frantic => (60, 30, 2),
+        };
+
+        let shortening = (length.saturating_sub(1) / segments_per_millisecond) as u64;
+
+        Duration::from_millis(start.saturating_sub(shortening).max(minimum))
+    }
+}
+
+/// Size and aspect ratio of the arena.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum ArenaSize {
+    Small,
+    /// The size of the original game.
+    #[default]
+    Medium,
+    Large,
+    /// As high as [`ArenaSize::Medium`], but wide enough to fill a 21:9 screen.
+    Ultrawide,
+}
+
+impl ArenaSize {
+    pub const ALL: [Self; 4] = [Self::Small, Self::Medium, Self::Large, Self::Ultrawide];
+
+    /// Horizontal position of the right wall. The left wall is at 0.
+    pub fn width(self) -> i32 {
+        match self {
+            ArenaSize::Small => 30,
+            ArenaSize::Medium => 40,
+            ArenaSize::Large => 52,
+            ArenaSize::Ultrawide => 56,
+        }
+    }
+
+    /// Vertical position of the top wall. The bottom wall is at 0.
+    pub fn height(self) -> i32 {
+        match self {
+            ArenaSize::Small => 16,
+            ArenaSize::Medium => 22,
+            ArenaSize::Large => 29,
+            ArenaSize::Ultrawide => 22,
+        }
+    }
+
+    /// Number of tiles from the left to the right edge of the walls.
+    pub fn tile_width(self) -> i32 {
+        (self.width() + 1) * CONSUMABLE_SCALE_FACTOR
+    }
+
+    /// Number of tiles from the bottom to the top edge of the walls.
+    pub fn tile_height(self) -> i32 {
+        (self.height() + 1) * CONSUMABLE_SCALE_FACTOR
+    }
+
+    /// The tile in the center of the arena, where the diplopod starts.
+    pub fn start_tile(self) -> Tile {
+        Tile {
+            x: self.tile_width() / 2,
+            y: self.tile_height() / 2,
+        }
+    }
+}
+
+/// Who plays a game.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Mode {
+    /// A single diplopod, like in the original game.
+    #[default]
+    Single,
+    /// Two diplopods compete on the same arena. Running into the other diplopod is deadly, the
+    /// last one alive wins.
+    Versus,
+    /// Two diplopods play together. They share the goal of the level, or a combined length of
+    /// [`COOP_GOAL_LENGTH`] if it has none, and the immunity of an antidote. The game goes on
+    /// until both are dead.
+    Coop,
+    /// Like [`Mode::Versus`], but the second diplopod is steered by the computer (see
+    /// [`computer`]).
+    Computer,
+}
+
+impl Mode {
+    pub const ALL: [Self; 4] = [Self::Single, Self::Versus, Self::Coop, Self::Computer];
+
+    /// Number of diplopods in the arena.
+    pub fn players(self) -> usize {
+        match self {
+            Mode::Single => 1,
+            Mode::Versus | Mode::Coop | Mode::Computer => 2,
+        }
+    }
+
+    /// Returns `true` if the diplopods compete with each other.
+    pub fn is_competitive(self) -> bool {
+        matches!(self, Mode::Versus | Mode::Computer)
+    }
+}
+
+/// Options of a game that change how the same input plays out. They are stored in every replay.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Rules {
+    pub movement: Movement,
+    /// Ignores directions that would turn the head straight back into its neck.
+    pub reverse_protection: bool,
+    pub progression: Progression,
+    /// Size of the bordered arena. It is ignored when a [`Level`] is played.
+    pub arena: ArenaSize,
+    /// Removes the walls of the bordered arena, so the diplopod leaves it on one side and comes
+    /// back on the other. Levels decide this on their own.
+    pub wrap_around: bool,
+    pub mode: Mode,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Food,
+    Poison,
+    Superfood,
+    Antidote,
+}
+
+/// Something on the arena that can be eaten. The `id` stays the same while the consumable
+/// exists, even if it moves.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Consumable {
+    pub id: u32,
+    pub kind: Kind,
+    pub position: Position,
+}
+
+/// Input of all players for a single tick.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Input {
+    /// Requested direction of every player. `Direction::NONE` keeps the current direction.
+    pub directions: [Direction; MAX_PLAYERS],
+}
+
+/// Things that happened during a tick and may be presented to the player.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    AteFood {
+        player: usize,
+    },
+    AteSuperfood {
+        player: usize,
+        growth: u32,
+    },
+    AtePoison {
+        player: usize,
+    },
+    AteAntidote {
+        player: usize,
+        /// No diplopod was immune before.
+        immunity_started: bool,
+    },
+    SpecialSpawn,
+    /// A player of a cooperative game died while the other one goes on.
+    Died {
+        player: usize,
+    },
+    GameOver,
+    /// The goal of the level was reached, which ends the game as well.
+    GoalReached,
+}
+
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    seed: u64,
+    rules: Rules,
+    rng: StdRng,
+    replay: Replay,
+    tick: u64,
+    elapsed: Duration,
+    diplopods: Vec<Diplopod>,
+    level: Level,
+    consumables: Vec<Consumable>,
+    next_id: u32,
+    last_special_spawn: u32,
+    game_over: bool,
+    winner: Option<usize>,
+}
+
+impl Simulation {
+    /// Creates a new game with a bordered arena, a diplopod in the center and randomly placed
+    /// food and poison.
+    pub fn new(seed: u64, rules: Rules) -> Self {
+        let level = if rules.wrap_around {
+            Level::open(rules.arena)
+        } else {
+            Level::bordered(rules.arena)
+        };
+
+        Self::create(seed, rules, level, None)
+    }
+
+    /// Creates a new game on the given level. The path of the level is stored in the replay,
+    /// so that it can be loaded again for the playback.
+    pub fn with_level(seed: u64, rules: Rules, level: Level, path: &str) -> Self {
+        Self::create(seed, rules, level, Some(path.to_string()))
+    }
+
+    fn create(seed: u64, rules: Rules, mut level: Level, path: Option<String>) -> Self {
+        // players that work together always have a goal to share
+        if rules.mode == Mode::Coop && level.goal.is_none() {
+            level.goal = Some(Goal::Length(COOP_GOAL_LENGTH));
+        }
+
+        let diplopods = level
+            .starts(rules.mode.players())
+            .into_iter()
+            .enumerate()
+            .map(|(player, start)| {
+                // the diplopods start opposite of each other
+                let direction = if player == 0 {
+                    level.direction
+                } else {
+                    level.direction.reversed()
+                };
+
+                Diplopod::new(start, direction)
+            })
+            .collect();
+
+        let mut simulation = Simulation {
+            seed,
+            rules,
+            rng: StdRng::seed_from_u64(seed),
+            replay: Replay::new(seed, rules, path),
+            tick: 0,
+            elapsed: Duration::ZERO,
+            diplopods,
+            level,
+            consumables: Vec::new(),
+            next_id: 0,
+            last_special_spawn: 0,
+            game_over: false,
+            winner: None,
+        };
+
+        for position in simulation.level.food.clone() {
+            simulation.place(Kind::Food, position);
+        }
+
+        for position in simulation.level.poison.clone() {
+            simulation.place(Kind::Poison, position);
+        }
+
+        let mut free_positions = simulation.randomized_free_positions();
+
+        for _ in 0..simulation.level.spawn.food {
+            if let Some(position) = free_positions.pop() {
+                simulation.place(Kind::Food, position);
+            }
+        }
+
+        for _ in 0..simulation.level.spawn.poison {
+            if let Some(position) = free_positions.pop() {
+                simulation.place(Kind::Poison, position);
+            }
+        }
+
+        simulation
+    }
+
+    /// The seed of the random number generator this game was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Number of ticks that have been simulated.
+    pub fn ticks(&self) -> u64 {
+        self.tick
+    }
+
+    /// Time that has been simulated if every tick took [`Simulation::tick_duration`].
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Time until the next tick, which depends on the progression and the length of the
+    /// longest diplopod.
+    pub fn tick_duration(&self) -> Duration {
+        let length = self
+            .diplopods
+            .iter()
+            .map(|diplopod| diplopod.segments().len())
+            .max()
+            .unwrap_or_default();
+
+        self.rules.progression.tick_duration(length)
+    }
+
+    /// Remaining time of immunity of a player if the current tick duration does not change.
+    pub fn immunity_remaining(&self, player: usize) -> Duration {
+        self.tick_duration() * self.diplopods[player].immunity()
+    }
+
+    /// The input of all ticks so far.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// The diplopod of the first player.
+    pub fn diplopod(&self) -> &Diplopod {
+        &self.diplopods[0]
+    }
+
+    /// The diplopods of all players, in the order of the players.
+    pub fn diplopods(&self) -> &[Diplopod] {
+        &self.diplopods
+    }
+
+    /// Combined length of all diplopods, which is the shared score of a cooperative game.
+    pub fn length(&self) -> usize {
+        self.diplopods
+            .iter()
+            .map(|diplopod| diplopod.segments().len())
+            .sum()
+    }
+
+    /// The player who won a game with several players once it is over. There is no winner if
+    /// all diplopods died at the same time with the same length.
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn walls(&self) -> &[Position] {
+        &self.level.walls
+    }
+
+    pub fn consumables(&self) -> &[Consumable] {
+        &self.consumables
+    }
+
+    /// Advances the game by one tick and returns everything that happened during it. Once the
+    /// game is over, further ticks have no effect.
+    pub fn tick(&mut self, input: Input) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        if self.game_over {
+            return events;
+        }
+
+        self.tick += 1;
+        self.elapsed += self.tick_duration();
+        self.replay.record(self.tick, input);
+
+        let mut dead = Vec::new();
+        let previous_heads: Vec<Tile> = self.diplopods.iter().map(Diplopod::head).collect();
+
+        for (player, diplopod) in self
+            .diplopods
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, diplopod)| diplopod.is_alive())
+        {
+            diplopod.steer(input.directions[player], self.rules, &self.level);
+            diplopod.wear_off_immunity();
+
+            if !diplopod.advance(&self.level) {
+                dead.push(player);
+            }
+        }
+
+        // a head that runs into another diplopod is bitten off, even if it is the other head.
+        // Heads that swap their tiles run into each other as well.
+        for player in 0..self.diplopods.len() {
+            let head = self.diplopods[player].head();
+            let swapped = |other: usize, diplopod: &Diplopod| {
+                head == previous_heads[other] && diplopod.head() == previous_heads[player]
+            };
+
+            if self.diplopods[player].is_alive()
+                && !dead.contains(&player)
+                && self.diplopods.iter().enumerate().any(|(other, diplopod)| {
+                    other != player
+                        && (diplopod.segments().contains(&head) || swapped(other, diplopod))
+                })
+            {
+                dead.push(player);
+            }
+        }
+
+        for player in 0..self.diplopods.len() {
+            if self.diplopods[player].is_alive()
+                && !dead.contains(&player)
+                && !self.check_collision(player, &mut events)
+            {
+                dead.push(player);
+            }
+        }
+
+        for &player in &dead {
+            self.diplopods[player].die();
+        }
+
+        // in a cooperative game the survivor goes on alone
+        if !dead.is_empty()
+            && (self.rules.mode != Mode::Coop || !self.diplopods.iter().any(Diplopod::is_alive))
+        {
+            self.decide_winner();
+            self.end(&mut events);
+            return events;
+        }
+
+        for &player in &dead {
+            events.push(Event::Died { player });
+        }
+
+        if self.tick.is_multiple_of(ANTIDOTE_MOVE_INTERVAL) {
+            self.move_antidotes();
+        }
+
+        if self.is_goal_reached() {
+            self.game_over = true;
+            events.push(Event::GoalReached);
+        }
+
+        events
+    }
+
+    /// Goals of levels do not count when the players compete. Players that work together reach
+    /// a length goal with their combined length.
+    fn is_goal_reached(&self) -> bool {
+        if self.rules.mode.is_competitive() {
+            return false;
+        }
+
+        match self.level.goal {
+            None => false,
+            Some(Goal::Length(length)) => self.length() >= length,
+            Some(Goal::ClearPoison) => !self.consumables.iter().any(|c| c.kind == Kind::Poison),
+            Some(Goal::Survive(duration)) => self.elapsed >= duration,
+        }
+    }
+
+    /// The last one alive wins. If all diplopods died, the longest one wins.
+    fn decide_winner(&mut self) {
+        if !self.rules.mode.is_competitive() {
+            return;
+        }
+
+        let alive: Vec<usize> = (0..self.diplopods.len())
+            .filter(|&player| self.diplopods[player].is_alive())
+            .collect();
+
+        self.winner = match alive[..] {
+            [winner] => Some(winner),
+            _ => {
+                let length = |player: usize| self.diplopods[player].segments().len();
+                let longest = (0..self.diplopods.len()).max_by_key(|&player| length(player));
+
+                longest.filter(|&winner| {
+                    (0..self.diplopods.len())
+                        .all(|player| player == winner || length(player) < length(winner))
+                })
+            }
+        };
+    }
+
+    /// Lets a player eat everything at the position of the head. Returns `false` if the
+    /// diplopod died.
+    fn check_collision(&mut self, player: usize, events: &mut Vec<Event>) -> bool {
+        let head_position: Position = self.diplopods[player].head().into();
+
+        if self.level.walls.contains(&head_position) {
+            return false;
+        }
+
+        let eaten: Vec<Consumable> = self
+            .consumables
+            .iter()
+            .filter(|c| c.position == head_position)
+            .copied()
+            .collect();
+
+        for consumable in eaten {
+            match consumable.kind {
+                Kind::Food => {
+                    self.remove(consumable.id);
+                    self.diplopods[player].grow(1);
+                    events.push(Event::AteFood { player });
+                    self.spawn_consumables(true, events);
+                }
+
+                Kind::Superfood => {
+                    self.remove(consumable.id);
+                    let growth = self.rng.random_range(2..10);
+                    self.diplopods[player].grow(growth);
+                    events.push(Event::AteSuperfood { player, growth });
+                    self.spawn_consumables(false, events);
+                }
+
+                Kind::Poison => {
+                    if self.diplopods[player].is_immune() {
+                        self.remove(consumable.id);
+                        self.diplopods[player].grow(1);
+                        events.push(Event::AtePoison { player });
+                        self.spawn_consumables(false, events);
+                    } else {
+                        return false;
+                    }
+                }
+
+                Kind::Antidote => {
+                    self.remove(consumable.id);
+                    let immunity_started = !self.diplopods.iter().any(Diplopod::is_immune);
+
+                    // players that work together share the antidote
+                    for (other, diplopod) in self.diplopods.iter_mut().enumerate() {
+                        if other == player || (self.rules.mode == Mode::Coop && diplopod.is_alive())
+                        {
+                            diplopod.extend_immunity();
+                        }
+                    }
+                    events.push(Event::AteAntidote {
+                        player,
+                        immunity_started,
+                    });
+                }
+            }
+        }
+
+        true
+    }
+
+    fn spawn_consumables(&mut self, regular: bool, events: &mut Vec<Event>) {
+        let mut free_positions = self.randomized_free_positions();
+
+        if regular {
+            if let Some(position) = free_positions.pop() {
+                self.place(Kind::Food, position);
+            }
+
+            if let Some(position) = free_positions.pop() {
+                self.place(Kind::Poison, position);
+            }
+        }
+
+        // the diplopods grow together towards the next special spawn
+        let length = self
+            .diplopods
+            .iter()
+            .map(|diplopod| diplopod.segments().len() as u32)
+            .sum::<u32>();
+        let interval = self.level.spawn.special_interval;
+
+        if interval > 0 && length - self.last_special_spawn > interval {
+            self.last_special_spawn = (length / interval) * interval;
+
+            self.consumables.retain(|c| c.kind != Kind::Superfood);
+
+            if self.last_special_spawn.is_multiple_of(interval * 2) {
+                self.consumables.retain(|c| c.kind != Kind::Antidote);
+
+                if let Some(position) = free_positions.pop() {
+                    self.place(Kind::Antidote, position);
+                }
+            }
+
+            if let Some(position) = free_positions.pop() {
+                self.place(Kind::Superfood, position);
+            }
+
+            events.push(Event::SpecialSpawn);
+        }
+    }
+
+    fn move_antidotes(&mut self) {
+        let segment_positions: Vec<Position> = self
+            .diplopods
+            .iter()
+            .flat_map(Diplopod::segments)
+            .map(|&t| t.into())
+            .collect();
+
+        for consumable in self
+            .consumables
+            .iter_mut()
+            .filter(|c| c.kind == Kind::Antidote)
+        {
+            let mut new_pos = consumable.position;
+            match self.rng.random_range(0..4) {
+                0 => new_pos.x -= 1,
+                1 => new_pos.x += 1,
+                2 => new_pos.y -= 1,
+                3 => new_pos.y += 1,
+                _ => (),
+            }
+            new_pos = self.level.wrap_position(new_pos);
+
+            if !self.level.is_inside(new_pos)
+                || self.level.walls.contains(&new_pos)
+                || segment_positions.contains(&new_pos)
+            {
+                continue;
+            }
+
+            consumable.position = new_pos;
+        }
+    }
+
+    fn randomized_free_positions(&mut self) -> Vec<Position> {
+        let (width, height) = (self.level.width, self.level.height);
+        let mut free_positions: Vec<Position> =
+            Vec::with_capacity(((width + 1) * (height + 1)) as usize);
+
+        for x in 0..=width {
+            for y in 0..=height {
+                let position = Position { x, y };
+
+                if self.level.is_inside(position) {
+                    free_positions.push(position);
+                }
+            }
+        }
+
+        let occupied: Vec<Position> = self
+            .level
+            .walls
+            .iter()
+            .copied()
+            .chain(self.consumables.iter().map(|c| c.position))
+            .chain(
+                self.diplopods
+                    .iter()
+                    .flat_map(Diplopod::segments)
+                    .map(|&t| t.into()),
+            )
+            .collect();
+
+        free_positions.retain(|p| !occupied.contains(p));
+
+        free_positions.shuffle(&mut self.rng);
+
+        free_positions
+    }
+
+    fn place(&mut self, kind: Kind, position: Position) {
+        self.consumables.push(Consumable {
+            id: self.next_id,
+            kind,
+            position,
+        });
+        self.next_id += 1;
+    }
+
+    fn remove(&mut self, id: u32) {
+        self.consumables.retain(|c| c.id != id);
+    }
+
+    fn end(&mut self, events: &mut Vec<Event>) {
+        self.game_over = true;
+        events.push(Event::GameOver);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RIGHT: Direction = Direction { x: 1, y: 0 };
+    const LEFT: Direction = Direction { x: -1, y: 0 };
+    const UP: Direction = Direction { x: 0, y: 1 };
+    const DOWN: Direction = Direction { x: 0, y: -1 };
+
+    /// A small bordered arena without any random consumables, on which the diplopod starts at
+    /// the given position and moves to the right.
+    fn empty_game(start: Position) -> Simulation {
+        let mut level = Level::bordered(ArenaSize::Small);
+        level.start = level::start_tile(start);
+        level.direction = RIGHT;
+        level.spawn = level::SpawnRules {
+            food: 0,
+            poison: 0,
+            special_interval: 0,
+        };
+
+        Simulation::with_level(0, Rules::default(), level, "test")
+    }
+
+    fn steer(direction: Direction) -> Input {
+        Input {
+            directions: [direction, Direction::NONE],
+        }
+    }
+
+    /// The position the head of the first diplopod reaches with the next tick.
+    fn ahead(simulation: &Simulation) -> Position {
+        let diplopod = simulation.diplopod();
+        diplopod.head().step(diplopod.direction()).into()
+    }
+
+    fn count(simulation: &Simulation, kind: Kind) -> usize {
+        simulation
+            .consumables()
+            .iter()
+            .filter(|consumable| consumable.kind == kind)
+            .count()
+    }
+
+    #[test]
+    fn eating_food_grows_and_spawns_food_and_poison() {
+        let mut simulation = empty_game(Position { x: 10, y: 8 });
+        let position = ahead(&simulation);
+        simulation.place(Kind::Food, position);
+
+        let events = simulation.tick(Input::default());
+
+        assert_eq!(events, vec![Event::AteFood { player: 0 }]);
+        assert_eq!(simulation.diplopod().segments().len(), 2);
+        assert_eq!(count(&simulation, Kind::Food), 1);
+        assert_eq!(count(&simulation, Kind::Poison), 1);
+        assert!(
+            simulation
+                .consumables()
+                .iter()
+                .all(|c| c.position != position)
+        );
+    }
+
+    #[test]
+    fn eating_superfood_grows_by_its_growth() {
+        let mut simulation = empty_game(Position { x: 10, y: 8 });
+        let position = ahead(&simulation);
+        simulation.place(Kind::Superfood, position);
+
+        let events = simulation.tick(Input::default());
+
+        let [Event::AteSuperfood { player: 0, growth }] = events[..] else {
+            panic!("unexpected events {:?}", events);
+        };
+
+        assert!((2..10).contains(&growth));
+        assert_eq!(simulation.diplopod().segments().len(), 1 + growth as usize);
+        assert_eq!(count(&simulation, Kind::Superfood), 0);
+    }
+
+    #[test]
+    fn poison_is_deadly_without_immunity() {
+        let mut simulation = empty_game(Position { x: 10, y: 8 });
+        let position = ahead(&simulation);
+        simulation.place(Kind::Poison, position);
+
+        assert_eq!(simulation.tick(Input::default()), vec![Event::GameOver]);
+        assert!(!simulation.diplopod().is_alive());
+
+        // the game is over, further ticks change nothing
+        assert!(simulation.tick(Input::default()).is_empty());
+        assert_eq!(simulation.ticks(), 1);
+    }
+
+    #[test]
+    fn antidote_makes_poison_edible() {
+        let mut simulation = empty_game(Position { x: 10, y: 8 });
+        let position = ahead(&simulation);
+        simulation.place(Kind::Antidote, position);
+
+        assert_eq!(
+            simulation.tick(Input::default()),
+            vec![Event::AteAntidote {
+                player: 0,
+                immunity_started: true
+            }]
+        );
+        assert_eq!(simulation.diplopod().immunity(), IMMUNITY_TICKS);
+
+        // the head needs two ticks to cross a position
+        simulation.tick(Input::default());
+        let position = ahead(&simulation);
+        simulation.place(Kind::Poison, position);
+
+        assert_eq!(
+            simulation.tick(Input::default()),
+            vec![Event::AtePoison { player: 0 }]
+        );
+        assert!(simulation.diplopod().is_alive());
+        assert_eq!(simulation.diplopod().segments().len(), 2);
+        assert_eq!(simulation.diplopod().immunity(), IMMUNITY_TICKS - 2);
+    }
+
+    /// Two diplopods on a small bordered arena without any random consumables. The first one
+    /// moves to the right, the second one to the left.
+    fn two_player_game(mode: Mode, first: Position, second: Position) -> Simulation {
+        let mut level = empty_game(first).level;
+        level.second_start = Some(level::start_tile(second));
+
+        Simulation::with_level(
+            0,
+            Rules {
+                mode,
+                ..Rules::default()
+            },
+            level,
+            "test",
+        )
+    }
+
+    #[test]
+    fn antidote_is_shared_in_a_coop_game() {
+        let mut simulation = two_player_game(
+            Mode::Coop,
+            Position { x: 10, y: 8 },
+            Position { x: 20, y: 4 },
+        );
+        let position = ahead(&simulation);
+        simulation.place(Kind::Antidote, position);
+
+        assert_eq!(
+            simulation.tick(Input::default()),
+            vec![Event::AteAntidote {
+                player: 0,
+                immunity_started: true
+            }]
+        );
+        assert_eq!(simulation.diplopods()[0].immunity(), IMMUNITY_TICKS);
+        assert_eq!(simulation.diplopods()[1].immunity(), IMMUNITY_TICKS);
+    }
+
+    #[test]
+    fn survivor_plays_on_in_a_coop_game() {
+        let mut simulation = two_player_game(
+            Mode::Coop,
+            Position { x: 10, y: 8 },
+            Position { x: 1, y: 4 },
+        );
+
+        // the head needs two ticks to cross a position
+        assert!(simulation.tick(Input::default()).is_empty());
+        assert_eq!(
+            simulation.tick(Input::default()),
+            vec![Event::Died { player: 1 }]
+        );
+        assert!(simulation.diplopods()[0].is_alive());
+        assert!(!simulation.diplopods()[1].is_alive());
+
+        assert!(simulation.tick(Input::default()).is_empty());
+        let position = ahead(&simulation);
+        simulation.place(Kind::Poison, position);
+
+        assert_eq!(simulation.tick(Input::default()), vec![Event::GameOver]);
+        assert!(!simulation.diplopods()[0].is_alive());
+        assert_eq!(simulation.winner(), None);
+    }
+
+    #[test]
+    fn coop_game_reaches_a_length_goal_together() {
+        let mut simulation = two_player_game(
+            Mode::Coop,
+            Position { x: 10, y: 8 },
+            Position { x: 20, y: 4 },
+        );
+
+        assert_eq!(
+            simulation.level().goal,
+            Some(Goal::Length(COOP_GOAL_LENGTH))
+        );
+
+        simulation.level.goal = Some(Goal::Length(3));
+        let position = ahead(&simulation);
+        simulation.place(Kind::Food, position);
+
+        assert_eq!(
+            simulation.tick(Input::default()),
+            vec![Event::AteFood { player: 0 }, Event::GoalReached]
+        );
+        assert_eq!(simulation.length(), 3);
+    }
+
+    #[test]
+    fn last_one_alive_wins_a_versus_game() {
+        let mut simulation = two_player_game(
+            Mode::Versus,
+            Position { x: 10, y: 8 },
+            Position { x: 1, y: 4 },
+        );
+
+        // the head needs two ticks to cross a position
+        assert!(simulation.tick(Input::default()).is_empty());
+        assert_eq!(simulation.tick(Input::default()), vec![Event::GameOver]);
+        assert!(simulation.diplopods()[0].is_alive());
+        assert_eq!(simulation.winner(), Some(0));
+    }
+
+    #[test]
+    fn longest_wins_when_both_die() {
+        let mut simulation = two_player_game(
+            Mode::Versus,
+            Position { x: 1, y: 8 },
+            Position { x: 1, y: 4 },
+        );
+        simulation.diplopods[0].grow(2);
+
+        assert!(simulation.tick(steer(LEFT)).is_empty());
+        assert_eq!(simulation.tick(steer(LEFT)), vec![Event::GameOver]);
+        assert!(!simulation.diplopods()[0].is_alive());
+        assert!(!simulation.diplopods()[1].is_alive());
+        assert_eq!(simulation.winner(), Some(0));
+    }
+
+    #[test]
+    fn tie_has_no_winner() {
+        let mut simulation = two_player_game(
+            Mode::Versus,
+            Position { x: 1, y: 8 },
+            Position { x: 1, y: 4 },
+        );
+
+        assert!(simulation.tick(steer(LEFT)).is_empty());
+        assert_eq!(simulation.tick(steer(LEFT)), vec![Event::GameOver]);
+        assert_eq!(simulation.winner(), None);
+    }
+
+    #[test]
+    fn running_into_the_other_diplopod_is_deadly() {
+        let mut simulation = two_player_game(
+            Mode::Versus,
+            Position { x: 10, y: 8 },
+            Position { x: 12, y: 8 },
+        );
+        simulation.diplopods[1].grow(6);
+
+        // the second diplopod turns away and leaves its tail behind for the first one
+        let input = Input {
+            directions: [Direction::NONE, UP],
+        };
+
+        for _ in 0..3 {
+            assert!(simulation.tick(input).is_empty());
+        }
+
+        assert_eq!(simulation.tick(input), vec![Event::GameOver]);
+        assert!(!simulation.diplopods()[0].is_alive());
+        assert!(simulation.diplopods()[1].is_alive());
+        assert_eq!(simulation.winner(), Some(1));
+    }
+
+    #[test]
+    fn heads_that_swap_their_tiles_collide() {
+        let mut simulation = two_player_game(
+            Mode::Versus,
+            Position { x: 10, y: 8 },
+            Position { x: 20, y: 4 },
+        );
+        let head = simulation.diplopod().head();
+        simulation.diplopods[1] = Diplopod::new(head.step(RIGHT), LEFT);
+
+        assert_eq!(simulation.tick(Input::default()), vec![Event::GameOver]);
+        assert!(!simulation.diplopods()[0].is_alive());
+        assert!(!simulation.diplopods()[1].is_alive());
+        assert_eq!(simulation.winner(), None);
+    }
+
+    #[test]
+    fn immunity_wears_off() {
+        let mut simulation = empty_game(Position { x: 2, y: 8 });
+        simulation.diplopods[0].extend_immunity();
+
+        for _ in 0..IMMUNITY_TICKS {
+            simulation.tick(steer(if simulation.ticks() % 80 < 40 {
+                RIGHT
+            } else {
+                LEFT
+            }));
+        }
+
+        assert!(simulation.diplopod().is_alive());
+        assert!(!simulation.diplopod().is_immune());
+    }
+
+    #[test]
+    fn running_into_a_wall_ends_the_game() {
+        let mut simulation = empty_game(Position { x: 1, y: 8 });
+
+        // the head needs two ticks to cross a position
+        assert!(simulation.tick(steer(LEFT)).is_empty());
+        assert_eq!(simulation.tick(steer(LEFT)), vec![Event::GameOver]);
+        assert!(!simulation.diplopod().is_alive());
+    }
+
+    #[test]
+    fn running_into_the_own_body_ends_the_game() {
+        let mut simulation = empty_game(Position { x: 10, y: 8 });
+        simulation.diplopods[0].grow(4);
+
+        // unfold the body to a straight line
+        for _ in 0..4 {
+            assert!(simulation.tick(steer(RIGHT)).is_empty());
+        }
+
+        assert!(simulation.tick(steer(UP)).is_empty());
+        assert!(simulation.tick(steer(LEFT)).is_empty());
+        assert_eq!(simulation.tick(steer(DOWN)), vec![Event::GameOver]);
+    }
+
+    #[test]
+    fn tick_duration_shrinks_with_the_length_down_to_a_minimum() {
+        let millis =
+            |progression: Progression, length: usize| progression.tick_duration(length).as_millis();
+
+        assert_eq!(Progression::Classic.tick_duration(1), TICK_DURATION);
+
+        // start, the first shortening, shortly before the minimum and far beyond it
+        assert_eq!(millis(Progression::Relaxed, 1), 100);
+        assert_eq!(millis(Progression::Relaxed, 4), 100);
+        assert_eq!(millis(Progression::Relaxed, 5), 99);
+        assert_eq!(millis(Progression::Relaxed, 100), 76);
+        assert_eq!(millis(Progression::Relaxed, 1000), 75);
+
+        assert_eq!(millis(Progression::Classic, 1), 75);
+        assert_eq!(millis(Progression::Classic, 4), 75);
+        assert_eq!(millis(Progression::Classic, 5), 74);
+        assert_eq!(millis(Progression::Classic, 100), 51);
+        assert_eq!(millis(Progression::Classic, 1000), 50);
+
+        assert_eq!(millis(Progression::Frantic, 1), 60);
+        assert_eq!(millis(Progression::Frantic, 2), 60);
+        assert_eq!(millis(Progression::Frantic, 3), 59);
+        assert_eq!(millis(Progression::Frantic, 60), 31);
+        assert_eq!(millis(Progression::Frantic, 1000), 30);
+
+        // a diplopod without segments plays at the start speed
+        assert_eq!(millis(Progression::Classic, 0), 75);
+    }
+
+    #[test]
+    fn same_seed_spawns_the_same_consumables() {
+        let mut first = Simulation::new(42, Rules::default());
+        let mut second = Simulation::new(42, Rules::default());
+
+        assert_eq!(first.consumables(), second.consumables());
+
+        let inputs = [UP, RIGHT, DOWN, LEFT];
+
+        for tick in 0..200 {
+            let input = steer(inputs[tick / 10 % inputs.len()]);
+            assert_eq!(first.tick(input), second.tick(input));
+            assert_eq!(first.consumables(), second.consumables());
+            assert_eq!(first.diplopod().segments(), second.diplopod().segments());
+        }
+    }
+
+    #[test]
+    fn different_seeds_spawn_different_consumables() {
+        let first = Simulation::new(1, Rules::default());
+        let second = Simulation::new(2, Rules::default());
+
+        assert_ne!(first.consumables(), second.consumables());
+    }
+}
@@ -0,0 +1,275 @@
+//! A player steered by the computer. It only looks at the state of the [`Simulation`] and
+//! chooses the direction for the next tick like a human player would, so it plays by the same
+//! rules. Its choices do not depend on chance, so a game against it can be replayed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+use super::diplopod::Diplopod;
+use super::{CONSUMABLE_SCALE_FACTOR, Direction, Kind, Movement, Position, Simulation, Tile};
+
+/// How well the computer plays.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Difficulty {
+    /// Heads for the nearest food without minding what is in the way.
+    Easy,
+    /// Heads for the nearest food, but avoids running into walls, poison and diplopods.
+    #[default]
+    Normal,
+    /// Finds the shortest path to the next food around walls, poison and diplopods. Without
+    /// such a path, it moves to where there is the most room.
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Self; 3] = [Self::Easy, Self::Normal, Self::Hard];
+}
+
+/// Chooses the direction of the diplopod of the given player for the next tick.
+pub fn direction(simulation: &Simulation, player: usize, difficulty: Difficulty) -> Direction {
+    let view = View::new(simulation, player);
+    let diplopod = &simulation.diplopods()[player];
+
+    let directions: Vec<Direction> = view
+        .directions(diplopod.direction())
+        .into_iter()
+        // turning into the neck is never a good idea
+        .filter(|&direction| {
+            diplopod.segments().get(1) != Some(&view.step(diplopod.head(), direction))
+        })
+        .collect();
+
+    let safe: Vec<Direction> = directions
+        .iter()
+        .copied()
+        .filter(|&direction| view.is_free(view.step(diplopod.head(), direction)))
+        .collect();
+
+    let chosen = match difficulty {
+        Difficulty::Easy => view.closest(diplopod, &directions),
+        Difficulty::Normal => view.closest(diplopod, &safe),
+        Difficulty::Hard => view
+            .shortest_path(diplopod, &safe)
+            .or_else(|| view.roomiest(diplopod, &safe)),
+    };
+
+    chosen.unwrap_or(Direction::NONE)
+}
+
+/// What the computer knows about the arena.
+struct View<'a> {
+    simulation: &'a Simulation,
+    movement: Movement,
+    /// Positions of walls and, unless the diplopod is immune, poison.
+    blocked: HashSet<Position>,
+    /// Tiles of all diplopods.
+    occupied: HashSet<Tile>,
+    targets: Vec<Position>,
+}
+
+impl<'a> View<'a> {
+    fn new(simulation: &'a Simulation, player: usize) -> Self {
+        let immune = simulation.diplopods()[player].is_immune();
+
+        let blocked = simulation
+            .walls()
+            .iter()
+            .copied()
+            .chain(
+                simulation
+                    .consumables()
+                    .iter()
+                    .filter(|consumable| consumable.kind == Kind::Poison && !immune)
+                    .map(|consumable| consumable.position),
+            )
+            .collect();
+
+        let occupied = simulation
+            .diplopods()
+            .iter()
+            .flat_map(Diplopod::segments)
+            .copied()
+            .collect();
+
+        let targets = simulation
+            .consumables()
+            .iter()
+            .filter(|consumable| consumable.kind != Kind::Poison)
+            .map(|consumable| consumable.position)
+            .collect();
+
+        Self {
+            simulation,
+            movement: simulation.rules().movement,
+            blocked,
+            occupied,
+            targets,
+        }
+    }
+
+    /// The directions the rules allow, starting with the current direction, so that it is kept
+    /// if it is as good as any other.
+    fn directions(&self, current: Direction) -> Vec<Direction> {
+        let mut directions = vec![
+            Direction::new(1, 0),
+            Direction::new(0, 1),
+            Direction::new(-1, 0),
+            Direction::new(0, -1),
+        ];
+
+        if self.movement == Movement::EightWay {
+            directions.extend([
+                Direction::new(1, 1),
+                Direction::new(-1, 1),
+                Direction::new(-1, -1),
+                Direction::new(1, -1),
+            ]);
+        } else {
+            directions.retain(|&direction| current.is_none() || direction != current.reversed());
+        }
+
+        if let Some(index) = directions
+            .iter()
+            .position(|&direction| direction == current)
+        {
+            directions[..=index].rotate_right(1);
+        }
+
+        directions
+    }
+
+    fn step(&self, tile: Tile, direction: Direction) -> Tile {
+        self.simulation.level().wrap_tile(tile.step(direction))
+    }
+
+    /// Returns `true` if the diplopod survives moving onto the tile.
+    fn is_free(&self, tile: Tile) -> bool {
+        let position = Position::from(tile);
+
+        self.simulation.level().is_inside(position)
+            && !self.blocked.contains(&position)
+            && !self.occupied.contains(&tile)
+    }
+
+    /// Number of ticks from a tile to the nearest tile of a position if nothing is in the way.
+    fn distance(&self, from: Tile, to: Position) -> i32 {
+        let along = |from: i32, to: i32| {
+            let first = to * CONSUMABLE_SCALE_FACTOR;
+            let last = first + CONSUMABLE_SCALE_FACTOR - 1;
+
+            (first - from).max(from - last).max(0)
+        };
+
+        let (x, y) = (along(from.x, to.x), along(from.y, to.y));
+
+        if self.movement == Movement::EightWay {
+            x.max(y)
+        } else {
+            x + y
+        }
+    }
+
+    /// The direction that leads closest to the nearest target.
+    fn closest(&self, diplopod: &Diplopod, directions: &[Direction]) -> Option<Direction> {
+        directions.iter().copied().min_by_key(|&direction| {
+            let tile = self.step(diplopod.head(), direction);
+
+            self.targets
+                .iter()
+                .map(|&target| self.distance(tile, target))
+                .min()
+                .unwrap_or_default()
+        })
+    }
+
+    /// The first direction of the shortest path to a target, searched breadth-first over the
+    /// free tiles.
+    fn shortest_path(&self, diplopod: &Diplopod, directions: &[Direction]) -> Option<Direction> {
+        let mut visited = HashSet::from([diplopod.head()]);
+        let mut queue = VecDeque::new();
+
+        for &direction in directions {
+            let tile = self.step(diplopod.head(), direction);
+
+            if visited.insert(tile) {
+                queue.push_back((tile, direction));
+            }
+        }
+
+        while let Some((tile, first)) = queue.pop_front() {
+            if self.targets.contains(&Position::from(tile)) {
+                return Some(first);
+            }
+
+            for direction in self.directions(Direction::NONE) {
+                let next = self.step(tile, direction);
+
+                if self.is_free(next) && visited.insert(next) {
+                    queue.push_back((next, first));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The direction with the most free tiles that can be reached from it.
+    fn roomiest(&self, diplopod: &Diplopod, directions: &[Direction]) -> Option<Direction> {
+        directions
+            .iter()
+            .copied()
+            .rev()
+            .max_by_key(|&direction| self.room(self.step(diplopod.head(), direction)))
+    }
+
+    /// Number of free tiles that can be reached from the given tile.
+    fn room(&self, start: Tile) -> usize {
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(tile) = queue.pop_front() {
+            for direction in self.directions(Direction::NONE) {
+                let next = self.step(tile, direction);
+
+                if self.is_free(next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        visited.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Event, Input, MAX_PLAYERS, Rules};
+
+    /// Number of ticks the computer has to survive, while food and poison pile up.
+    const TICKS: u64 = 1000;
+
+    #[test]
+    fn hard_computer_survives_in_bordered_arena() {
+        for seed in 0..5 {
+            // without wrap around the game is played in `Level::bordered`
+            let mut simulation = Simulation::new(seed, Rules::default());
+
+            for _ in 0..TICKS {
+                let mut directions = [Direction::NONE; MAX_PLAYERS];
+                directions[0] = direction(&simulation, 0, Difficulty::Hard);
+
+                let events = simulation.tick(Input { directions });
+
+                assert!(
+                    !events.contains(&Event::GameOver),
+                    "seed {} died after {} ticks",
+                    seed,
+                    simulation.ticks()
+                );
+            }
+
+            assert!(simulation.length() > 10, "seed {} hardly ate", seed);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use super::level::Level;
+use super::{Direction, IMMUNITY_TICKS, Movement, Rules, Tile};
+
+/// The body of a diplopod. The first segment is the head.
+#[derive(Clone, Debug)]
+pub struct Diplopod {
+    segments: Vec<Tile>,
+    direction: Direction,
+    immunity: u32,
+    alive: bool,
+}
+
+impl Diplopod {
+    pub fn new(start: Tile, direction: Direction) -> Self {
+        Self {
+            segments: vec![start],
+            direction,
+            immunity: 0,
+            alive: true,
+        }
+    }
+
+    pub fn head(&self) -> Tile {
+        self.segments[0]
+    }
+
+    pub fn segments(&self) -> &[Tile] {
+        &self.segments
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Remaining ticks of immunity.
+    pub fn immunity(&self) -> u32 {
+        self.immunity
+    }
+
+    pub fn is_immune(&self) -> bool {
+        self.immunity > 0
+    }
+
+    /// A dead diplopod stays on the arena, but neither moves nor eats anymore.
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    /// Changes the direction unless the rules forbid it. Only [`Movement::EightWay`] allows
+    /// diagonal directions and reversing, the reverse protection prevents moving the head onto
+    /// the neck in any direction.
+    pub(super) fn steer(&mut self, direction: Direction, rules: Rules, level: &Level) {
+        if direction.is_none() {
+            return;
+        }
+
+        if rules.movement != Movement::EightWay
+            && (direction.is_diagonal() || direction == self.direction.reversed())
+        {
+            return;
+        }
+
+        if rules.reverse_protection
+            && self.segments.get(1) == Some(&level.wrap_tile(self.head().step(direction)))
+        {
+            return;
+        }
+
+        self.direction = direction;
+    }
+
+    /// Moves the head one tile into the current direction and lets the body follow. Returns
+    /// `false` if the head ran into the body.
+    pub(super) fn advance(&mut self, level: &Level) -> bool {
+        if self.direction.is_none() {
+            return true;
+        }
+
+        let head = level.wrap_tile(self.head().step(self.direction));
+        let bitten = self.segments.contains(&head);
+
+        self.segments.pop();
+        self.segments.insert(0, head);
+
+        !bitten
+    }
+
+    /// New segments are stacked onto the tail and unfold while the diplopod moves.
+    pub(super) fn grow(&mut self, amount: u32) {
+        let tail = *self.segments.last().unwrap();
+        for _ in 0..amount {
+            self.segments.push(tail);
+        }
+    }
+
+    pub(super) fn wear_off_immunity(&mut self) {
+        self.immunity = self.immunity.saturating_sub(1);
+    }
+
+    pub(super) fn extend_immunity(&mut self) {
+        self.immunity += IMMUNITY_TICKS;
+    }
+
+    pub(super) fn die(&mut self) {
+        self.alive = false;
+        self.immunity = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::ArenaSize;
+
+    const RIGHT: Direction = Direction { x: 1, y: 0 };
+    const LEFT: Direction = Direction { x: -1, y: 0 };
+    const UP: Direction = Direction { x: 0, y: 1 };
+    const DOWN: Direction = Direction { x: 0, y: -1 };
+
+    /// A diplopod whose head is at the first tile, heading right with its neck to the left.
+    fn diplopod(segments: &[(i32, i32)]) -> Diplopod {
+        Diplopod {
+            segments: segments.iter().map(|&(x, y)| Tile { x, y }).collect(),
+            direction: RIGHT,
+            immunity: 0,
+            alive: true,
+        }
+    }
+
+    fn rules(movement: Movement, reverse_protection: bool) -> Rules {
+        Rules {
+            movement,
+            reverse_protection,
+            ..Rules::default()
+        }
+    }
+
+    fn steered(
+        mut diplopod: Diplopod,
+        direction: Direction,
+        rules: Rules,
+        level: &Level,
+    ) -> Direction {
+        diplopod.steer(direction, rules, level);
+        diplopod.direction()
+    }
+
+    #[test]
+    fn four_way_ignores_reversing() {
+        let level = Level::bordered(ArenaSize::Small);
+        let diplopod = diplopod(&[(10, 10), (9, 10), (8, 10)]);
+
+        for reverse_protection in [false, true] {
+            let rules = rules(Movement::FourWay, reverse_protection);
+
+            assert_eq!(steered(diplopod.clone(), LEFT, rules, &level), RIGHT);
+            assert_eq!(steered(diplopod.clone(), UP, rules, &level), UP);
+        }
+    }
+
+    #[test]
+    fn four_way_ignores_diagonals() {
+        let level = Level::bordered(ArenaSize::Small);
+        let diplopod = diplopod(&[(10, 10), (9, 10), (8, 10)]);
+        let rules = rules(Movement::FourWay, false);
+
+        assert_eq!(
+            steered(diplopod, Direction::new(1, 1), rules, &level),
+            RIGHT
+        );
+    }
+
+    #[test]
+    fn eight_way_reverses_into_the_neck_only_without_protection() {
+        let level = Level::bordered(ArenaSize::Small);
+        let diplopod = diplopod(&[(10, 10), (9, 10), (8, 10)]);
+
+        let unprotected = rules(Movement::EightWay, false);
+        assert_eq!(steered(diplopod.clone(), LEFT, unprotected, &level), LEFT);
+
+        let protected = rules(Movement::EightWay, true);
+        assert_eq!(steered(diplopod.clone(), LEFT, protected, &level), RIGHT);
+        assert_eq!(steered(diplopod, DOWN, protected, &level), DOWN);
+    }
+
+    #[test]
+    fn protection_ignores_diagonal_back_steps_into_the_neck() {
+        let level = Level::bordered(ArenaSize::Small);
+        // heading up and to the right, the neck is down and to the left
+        let mut diplopod = diplopod(&[(10, 10), (9, 9), (8, 8)]);
+        diplopod.direction = Direction::new(1, 1);
+        let rules = rules(Movement::EightWay, true);
+
+        let back = Direction::new(-1, -1);
+        assert_eq!(
+            steered(diplopod.clone(), back, rules, &level),
+            diplopod.direction
+        );
+
+        // stepping back next to the neck is allowed
+        assert_eq!(steered(diplopod.clone(), LEFT, rules, &level), LEFT);
+        assert_eq!(steered(diplopod.clone(), DOWN, rules, &level), DOWN);
+        let sideways = Direction::new(-1, 1);
+        assert_eq!(steered(diplopod, sideways, rules, &level), sideways);
+    }
+
+    #[test]
+    fn protection_lets_a_diplopod_without_neck_reverse() {
+        let level = Level::bordered(ArenaSize::Small);
+        let diplopod = diplopod(&[(10, 10)]);
+        let rules = rules(Movement::EightWay, true);
+
+        assert_eq!(steered(diplopod, LEFT, rules, &level), LEFT);
+    }
+
+    #[test]
+    fn protection_finds_the_neck_across_the_edge() {
+        let level = Level::open(ArenaSize::Small);
+        let right_edge = level.tile_width() - 1;
+        // the head just wrapped around to the left edge, the neck is still on the right one
+        let diplopod = diplopod(&[(0, 10), (right_edge, 10), (right_edge - 1, 10)]);
+        let rules = rules(Movement::EightWay, true);
+
+        assert_eq!(steered(diplopod.clone(), LEFT, rules, &level), RIGHT);
+        assert_eq!(steered(diplopod, UP, rules, &level), UP);
+    }
+}
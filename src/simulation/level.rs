@@ -0,0 +1,605 @@
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::{ArenaSize, CONSUMABLE_SCALE_FACTOR, Direction, Position, Tile};
+
+/// Characters of the grid of a level file.
+const WALL: char = '#';
+const START: char = 'S';
+const FOOD: char = 'f';
+const POISON: char = 'p';
+const EMPTY: char = '.';
+
+/// Names of the directions in a level file.
+const DIRECTIONS: [(&str, i32, i32); 9] = [
+    ("none", 0, 0),
+    ("up", 0, 1),
+    ("down", 0, -1),
+    ("left", -1, 0),
+    ("right", 1, 0),
+    ("up-left", -1, 1),
+    ("up-right", 1, 1),
+    ("down-left", -1, -1),
+    ("down-right", 1, -1),
+];
+
+/// How many consumables are placed at random and how often superfood and antidotes appear.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SpawnRules {
+    /// Food that is placed at random when the game starts, in addition to the food of the level.
+    pub food: u32,
+    /// Poison that is placed at random when the game starts, in addition to the poison of the
+    /// level.
+    pub poison: u32,
+    /// Number of segments the diplopod has to grow before superfood appears. Every second time
+    /// an antidote appears as well. `0` disables both.
+    pub special_interval: u32,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        Self {
+            food: 16,
+            poison: 17,
+            special_interval: 16,
+        }
+    }
+}
+
+/// What has to be achieved to complete a level.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Goal {
+    /// Grow the diplopod to the given length.
+    Length(usize),
+    /// Eat all poison, which is only possible while being immune.
+    ClearPoison,
+    /// Stay alive for the given time.
+    Survive(Duration),
+}
+
+/// Layout of an arena together with the start of the diplopod, the rules for spawning
+/// consumables and an optional goal.
+///
+/// Levels are stored as text: a header with one `name value` pair per line, an empty line and
+/// a grid with one character per position. `#` is a wall, `S` the start of the diplopod, `f`
+/// food, `p` poison and `.` or a space an empty position. A second `S` is the start of the
+/// second player. The outermost rows and columns of the grid have to be walls, unless the level
+/// has `wrap-around on`.
+///
+/// ```text
+/// name Pillars
+/// direction right
+/// food 10
+/// poison 12
+/// special-interval 16
+/// goal length 20
+///
+/// ##########
+/// #........#
+/// #.S..#...#
+/// #....#.f.#
+/// #...p....#
+/// ##########
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Level {
+    pub name: String,
+    /// Horizontal position of the right wall. The left wall is at 0.
+    pub width: i32,
+    /// Vertical position of the top wall. The bottom wall is at 0.
+    pub height: i32,
+    pub walls: Vec<Position>,
+    pub start: Tile,
+    /// Start of the second player. Without it, the second player starts opposite of the first.
+    pub second_start: Option<Tile>,
+    /// Direction the diplopod moves in before the first input. [`Direction::NONE`] waits for
+    /// the player.
+    pub direction: Direction,
+    pub food: Vec<Position>,
+    pub poison: Vec<Position>,
+    pub spawn: SpawnRules,
+    /// Without a goal the game goes on until the diplopod dies.
+    pub goal: Option<Goal>,
+    /// The diplopod leaves the arena on one side and comes back on the other. The outermost
+    /// rows and columns don't have to be walls then.
+    pub wrap_around: bool,
+}
+
+impl Level {
+    /// An empty arena that is only surrounded by walls, like in the original game.
+    pub fn bordered(size: ArenaSize) -> Self {
+        let (width, height) = (size.width(), size.height());
+        let mut walls = Vec::new();
+
+        for x in 0..width + 1 {
+            walls.push(Position { x, y: 0 });
+            walls.push(Position { x, y: height });
+        }
+
+        for y in 1..height {
+            walls.push(Position { x: 0, y });
+            walls.push(Position { x: width, y });
+        }
+
+        Self {
+            name: format!("{:?}", size),
+            width,
+            height,
+            walls,
+            start: size.start_tile(),
+            second_start: None,
+            direction: Direction::NONE,
+            food: Vec::new(),
+            poison: Vec::new(),
+            spawn: SpawnRules::default(),
+            goal: None,
+            wrap_around: false,
+        }
+    }
+
+    /// An empty arena without walls whose edges are connected to the opposite ones.
+    pub fn open(size: ArenaSize) -> Self {
+        Self {
+            walls: Vec::new(),
+            wrap_around: true,
+            ..Self::bordered(size)
+        }
+    }
+
+    /// Number of tiles from the left to the right edge of the walls.
+    pub fn tile_width(&self) -> i32 {
+        (self.width + 1) * CONSUMABLE_SCALE_FACTOR
+    }
+
+    /// Number of tiles from the bottom to the top edge of the walls.
+    pub fn tile_height(&self) -> i32 {
+        (self.height + 1) * CONSUMABLE_SCALE_FACTOR
+    }
+
+    /// Returns `true` if the position lies within the outer walls. Without walls the whole grid
+    /// is inside.
+    pub fn is_inside(&self, position: Position) -> bool {
+        if self.wrap_around {
+            (0..=self.width).contains(&position.x) && (0..=self.height).contains(&position.y)
+        } else {
+            position.x >= 1
+                && position.x < self.width
+                && position.y >= 1
+                && position.y < self.height
+        }
+    }
+
+    /// Returns `true` if a diplopod can start on the tile, which must be inside the outer walls
+    /// and not on a wall.
+    pub fn is_free(&self, tile: Tile) -> bool {
+        let position = tile.into();
+        self.is_inside(position) && !self.walls.contains(&position)
+    }
+
+    /// The start of a diplopod closest to the given tile that isn't on a wall. The tile itself is
+    /// returned if it is free or if there is no free position at all.
+    fn nearest_free(&self, tile: Tile) -> Tile {
+        if self.is_free(tile) {
+            return tile;
+        }
+
+        let center: Position = tile.into();
+
+        for distance in 1..=self.width.max(self.height) {
+            for y in center.y - distance..=center.y + distance {
+                for x in center.x - distance..=center.x + distance {
+                    // only the positions on the ring around the center at this distance
+                    if (x - center.x).abs() != distance && (y - center.y).abs() != distance {
+                        continue;
+                    }
+
+                    let start = start_tile(Position { x, y });
+
+                    if self.is_free(start) {
+                        return start;
+                    }
+                }
+            }
+        }
+
+        tile
+    }
+
+    /// Start tiles of the given number of diplopods. The second diplopod starts opposite of the
+    /// first one unless the level has a second start. If the first start is close to the center,
+    /// both are moved apart. A start on a wall is moved to the nearest free position.
+    pub fn starts(&self, count: usize) -> Vec<Tile> {
+        if count < 2 {
+            return vec![self.nearest_free(self.start)];
+        }
+
+        if let Some(second) = self.second_start {
+            return vec![self.nearest_free(self.start), self.nearest_free(second)];
+        }
+
+        let opposite = |tile: Tile| Tile {
+            x: self.tile_width() - 1 - tile.x,
+            y: self.tile_height() - 1 - tile.y,
+        };
+
+        let mut first = self.nearest_free(self.start);
+        let distance = (opposite(first).x - first.x)
+            .abs()
+            .max((opposite(first).y - first.y).abs());
+
+        if distance < self.tile_width() / 4 {
+            let apart = Tile {
+                x: self.tile_width() / 4,
+                ..first
+            };
+
+            if self.is_free(apart) && self.is_free(opposite(apart)) {
+                first = apart;
+            }
+        }
+
+        vec![first, self.nearest_free(opposite(first))]
+    }
+
+    /// Moves a tile that left the arena to the opposite side, if the level wraps around.
+    pub fn wrap_tile(&self, tile: Tile) -> Tile {
+        if !self.wrap_around {
+            return tile;
+        }
+
+        Tile {
+            x: tile.x.rem_euclid(self.tile_width()),
+            y: tile.y.rem_euclid(self.tile_height()),
+        }
+    }
+
+    /// Moves a position that left the arena to the opposite side, if the level wraps around.
+    pub fn wrap_position(&self, position: Position) -> Position {
+        if !self.wrap_around {
+            return position;
+        }
+
+        Position {
+            x: position.x.rem_euclid(self.width + 1),
+            y: position.y.rem_euclid(self.height + 1),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s.lines();
+        let mut name = "Custom level".to_string();
+        let mut direction = Direction::NONE;
+        let mut spawn = SpawnRules::default();
+        let mut goal = None;
+        let mut wrap_around = false;
+
+        for line in lines.by_ref() {
+            if line.trim().is_empty() {
+                break;
+            }
+
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("Expected 'name value' instead of '{}'", line))?;
+            let value = value.trim();
+
+            match key {
+                "name" => name = value.to_string(),
+                "direction" => direction = parse_direction(value)?,
+                "food" => spawn.food = value.parse()?,
+                "poison" => spawn.poison = value.parse()?,
+                "special-interval" => spawn.special_interval = value.parse()?,
+                "goal" => goal = Some(parse_goal(value)?),
+                "wrap-around" => wrap_around = parse_on_off(value)?,
+                _ => return Err(anyhow!("Unknown key '{}' in level", key)),
+            }
+        }
+
+        let rows: Vec<Vec<char>> = lines
+            .map(|line| line.trim_end_matches('\r').chars().collect())
+            .collect();
+
+        let columns = rows.first().map(Vec::len).unwrap_or_default();
+
+        if rows.len() < 3 || columns < 3 {
+            return Err(anyhow!(
+                "The grid of a level needs at least 3 rows and columns"
+            ));
+        }
+
+        if rows.iter().any(|row| row.len() != columns) {
+            return Err(anyhow!("All rows of the grid must have the same length"));
+        }
+
+        let width = columns as i32 - 1;
+        let height = rows.len() as i32 - 1;
+
+        let mut level = Level {
+            name,
+            width,
+            height,
+            walls: Vec::new(),
+            start: Tile::default(),
+            second_start: None,
+            direction,
+            food: Vec::new(),
+            poison: Vec::new(),
+            spawn,
+            goal,
+            wrap_around,
+        };
+        let mut start = None;
+        let mut second_start = None;
+
+        for (row, characters) in rows.iter().enumerate() {
+            // the first row of the grid is the top of the arena
+            let y = height - row as i32;
+
+            for (x, &character) in characters.iter().enumerate() {
+                let position = Position { x: x as i32, y };
+
+                if character != WALL && !level.is_inside(position) {
+                    return Err(anyhow!("The grid must be surrounded by walls"));
+                }
+
+                match character {
+                    WALL => level.walls.push(position),
+                    START if second_start.is_some() => {
+                        return Err(anyhow!("The grid contains more than two starts"));
+                    }
+                    START if start.is_some() => second_start = Some(start_tile(position)),
+                    START => start = Some(position),
+                    FOOD => level.food.push(position),
+                    POISON => level.poison.push(position),
+                    EMPTY | ' ' => (),
+                    _ => return Err(anyhow!("Unknown character '{}' in grid", character)),
+                }
+            }
+        }
+
+        level.start = match start {
+            Some(position) => start_tile(position),
+            None => Tile {
+                x: level.tile_width() / 2,
+                y: level.tile_height() / 2,
+            },
+        };
+        level.second_start = second_start;
+
+        if !level.is_free(level.start) {
+            return Err(anyhow!(
+                "The grid needs a start, because its center is not free"
+            ));
+        }
+
+        if !level.starts(2).into_iter().all(|tile| level.is_free(tile)) {
+            return Err(anyhow!("The grid has no room for a second diplopod"));
+        }
+
+        Ok(level)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "name {}", self.name)?;
+
+        if !self.direction.is_none() {
+            writeln!(f, "direction {}", direction_name(self.direction))?;
+        }
+
+        writeln!(f, "food {}", self.spawn.food)?;
+        writeln!(f, "poison {}", self.spawn.poison)?;
+        writeln!(f, "special-interval {}", self.spawn.special_interval)?;
+
+        match self.goal {
+            None => (),
+            Some(Goal::Length(length)) => writeln!(f, "goal length {}", length)?,
+            Some(Goal::ClearPoison) => writeln!(f, "goal clear-poison")?,
+            Some(Goal::Survive(duration)) => writeln!(f, "goal survive {}", duration.as_secs())?,
+        }
+
+        if self.wrap_around {
+            writeln!(f, "wrap-around on")?;
+        }
+
+        writeln!(f)?;
+
+        let starts = [Some(self.start), self.second_start].map(|tile| tile.map(Position::from));
+
+        for y in (0..=self.height).rev() {
+            let row: String = (0..=self.width)
+                .map(|x| {
+                    let position = Position { x, y };
+
+                    if self.walls.contains(&position) {
+                        WALL
+                    } else if starts.contains(&Some(position)) {
+                        START
+                    } else if self.food.contains(&position) {
+                        FOOD
+                    } else if self.poison.contains(&position) {
+                        POISON
+                    } else {
+                        EMPTY
+                    }
+                })
+                .collect();
+
+            writeln!(f, "{}", row)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The tile of the head of a diplopod that starts at the given position. It is the upper right
+/// tile of the position, like in the center of the bordered arenas.
+pub fn start_tile(position: Position) -> Tile {
+    Tile {
+        x: position.x * CONSUMABLE_SCALE_FACTOR + CONSUMABLE_SCALE_FACTOR / 2,
+        y: position.y * CONSUMABLE_SCALE_FACTOR + CONSUMABLE_SCALE_FACTOR / 2,
+    }
+}
+
+fn parse_goal(value: &str) -> Result<Goal> {
+    let (kind, argument) = value.split_once(' ').unwrap_or((value, ""));
+
+    match kind {
+        "length" => Ok(Goal::Length(argument.trim().parse()?)),
+        "clear-poison" => Ok(Goal::ClearPoison),
+        "survive" => Ok(Goal::Survive(Duration::from_secs(argument.trim().parse()?))),
+        _ => Err(anyhow!("Invalid goal '{}' in level", value)),
+    }
+}
+
+fn parse_on_off(value: &str) -> Result<bool> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(anyhow!(
+            "Expected 'on' or 'off' instead of '{}' in level",
+            value
+        )),
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    DIRECTIONS
+        .iter()
+        .find(|&&(_, x, y)| Direction::new(x, y) == direction)
+        .map_or("none", |&(name, _, _)| name)
+}
+
+fn parse_direction(value: &str) -> Result<Direction> {
+    DIRECTIONS
+        .iter()
+        .find(|&&(name, _, _)| name == value)
+        .map(|&(_, x, y)| Direction::new(x, y))
+        .ok_or_else(|| anyhow!("Invalid direction '{}' in level", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PILLARS: &str = "\
+name Pillars
+direction right
+food 10
+poison 12
+special-interval 16
+goal length 20
+
+##########
+#........#
+#.S..#...#
+#....#.f.#
+#...p..S.#
+##########
+";
+
+    /// A level with the given header lines and grid.
+    fn parse(header: &str, grid: &str) -> Result<Level> {
+        format!("{}\n\n{}", header, grid).parse()
+    }
+
+    #[test]
+    fn level_survives_a_round_trip() {
+        let level: Level = PILLARS.parse().unwrap();
+
+        assert_eq!(level.to_string(), PILLARS);
+        assert_eq!(level.to_string().parse::<Level>().unwrap(), level);
+        assert_eq!(level.name, "Pillars");
+        assert_eq!(level.direction, Direction::new(1, 0));
+        assert_eq!(level.goal, Some(Goal::Length(20)));
+        assert_eq!(level.food, vec![Position { x: 7, y: 2 }]);
+        assert_eq!(level.poison, vec![Position { x: 4, y: 1 }]);
+        assert_eq!(level.start, start_tile(Position { x: 2, y: 3 }));
+        assert_eq!(
+            level.second_start,
+            Some(start_tile(Position { x: 7, y: 1 }))
+        );
+    }
+
+    #[test]
+    fn malformed_grids_are_rejected() {
+        // too small
+        assert!(parse("name Small", "###\n###").is_err());
+        // rows of different lengths
+        assert!(parse("name Ragged", "#####\n#S..#\n#..#\n#####").is_err());
+        // a gap in the outer walls
+        assert!(parse("name Open", "#####\n#S...\n#...#\n#####").is_err());
+        // more than two starts
+        assert!(parse("name Crowded", "#####\n#SSS#\n#...#\n#####").is_err());
+        // no walls are needed if the level wraps around
+        assert!(parse("wrap-around on", ".....\n.S...\n.....").is_ok());
+    }
+
+    #[test]
+    fn unknown_characters_are_rejected() {
+        let error = parse("name Strange", "#####\n#S.x#\n#...#\n#####").unwrap_err();
+
+        assert!(error.to_string().contains("Unknown character 'x'"));
+    }
+
+    #[test]
+    fn invalid_header_lines_are_rejected() {
+        let grid = "#####\n#S..#\n#...#\n#####";
+
+        for header in [
+            "name",
+            "colour red",
+            "direction sideways",
+            "food many",
+            "poison -1",
+            "special-interval often",
+            "goal length",
+            "goal length twenty",
+            "goal survive forever",
+            "goal fly",
+            "wrap-around maybe",
+        ] {
+            assert!(parse(header, grid).is_err(), "{}", header);
+        }
+    }
+
+    #[test]
+    fn start_in_the_center_must_be_free() {
+        assert!(parse("name Center", "#####\n#...#\n#...#\n#...#\n#####").is_ok());
+        assert!(parse("name Blocked", "#####\n#...#\n#.#.#\n#...#\n#####").is_err());
+    }
+
+    #[test]
+    fn second_diplopod_does_not_start_on_a_wall() {
+        // the position opposite of the start is a wall
+        let level = parse("name Maze", "#######\n#S....#\n#.....#\n#....##\n#######").unwrap();
+
+        let starts = level.starts(2);
+
+        assert_eq!(starts[0], start_tile(Position { x: 1, y: 3 }));
+        assert_eq!(starts[1], start_tile(Position { x: 4, y: 1 }));
+        assert!(starts.iter().all(|&tile| level.is_free(tile)));
+    }
+
+    #[test]
+    fn starts_on_walls_are_moved_to_the_nearest_free_position() {
+        let mut level = Level::bordered(ArenaSize::Small);
+        level.start = start_tile(Position { x: 0, y: 5 });
+        level.second_start = Some(start_tile(Position { x: 30, y: 5 }));
+
+        assert_eq!(
+            level.starts(2),
+            vec![
+                start_tile(Position { x: 1, y: 4 }),
+                start_tile(Position { x: 29, y: 4 })
+            ]
+        );
+    }
+}
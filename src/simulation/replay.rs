@@ -0,0 +1,340 @@
+use anyhow::{Context, Result, anyhow};
+use std::{fmt, str::FromStr};
+
+use super::{ArenaSize, Direction, Input, MAX_PLAYERS, Mode, Movement, Progression, Rules};
+
+/// First line of every replay, followed by the version of its format.
+const HEADER: &str = "diplopod replay";
+
+/// Version of the format that is written. It has to be increased whenever the format changes.
+/// Version 1 only contains the seed and the input of a single player, version 2 adds the rules,
+/// the level and the input of several players.
+const VERSION: u32 = 2;
+
+/// Input of a game together with the seed, the rules and the level it was played with. The input
+/// is stored as a list of changes, every change stays in effect until the next one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    seed: u64,
+    rules: Rules,
+    /// Path of the level, if the game was not played in a bordered arena.
+    level: Option<String>,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Step {
+    tick: u64,
+    input: Input,
+}
+
+impl Replay {
+    pub fn new(seed: u64, rules: Rules, level: Option<String>) -> Self {
+        Self {
+            seed,
+            rules,
+            level,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn level(&self) -> Option<&str> {
+        self.level.as_deref()
+    }
+
+    /// Records the input of a tick, if it differs from the input of the previous tick.
+    pub(super) fn record(&mut self, tick: u64, input: Input) {
+        if self.input(tick) != input {
+            self.steps.push(Step { tick, input });
+        }
+    }
+
+    /// Returns the input that was in effect during the given tick.
+    pub fn input(&self, tick: u64) -> Input {
+        let index = self.steps.partition_point(|step| step.tick <= tick);
+
+        if index == 0 {
+            Input::default()
+        } else {
+            self.steps[index - 1].input
+        }
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "movement {}", movement_name(self.rules.movement))?;
+        writeln!(
+            f,
+            "reverse-protection {}",
+            on_off(self.rules.reverse_protection)
+        )?;
+        writeln!(
+            f,
+            "progression {}",
+            progression_name(self.rules.progression)
+        )?;
+        writeln!(f, "arena {}", arena_name(self.rules.arena))?;
+        writeln!(f, "wrap-around {}", on_off(self.rules.wrap_around))?;
+        writeln!(f, "mode {}", mode_name(self.rules.mode))?;
+
+        if let Some(level) = &self.level {
+            writeln!(f, "level {}", level)?;
+        }
+
+        // the directions of the other players are only written if they are used
+        let players = self.rules.mode.players();
+
+        for step in &self.steps {
+            write!(f, "{}", step.tick)?;
+
+            for direction in &step.input.directions[..players] {
+                write!(f, " {} {}", direction.x, direction.y)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Replay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s.lines();
+
+        let version: u32 = lines
+            .next()
+            .and_then(|line| line.strip_prefix(HEADER))
+            .and_then(|version| version.strip_prefix(' '))
+            .ok_or_else(|| anyhow!("Not a diplopod replay"))?
+            .trim()
+            .parse()
+            .context("Invalid version of replay")?;
+
+        if !(1..=VERSION).contains(&version) {
+            return Err(anyhow!(
+                "Unsupported replay version {}, only versions up to {} can be played back",
+                version,
+                VERSION
+            ));
+        }
+
+        let seed = lines
+            .next()
+            .and_then(|line| line.strip_prefix("seed "))
+            .ok_or_else(|| anyhow!("Replay does not contain a seed"))?
+            .parse()
+            .context("Invalid seed in replay")?;
+
+        let mut lines = lines.filter(|line| !line.trim().is_empty()).peekable();
+
+        // replays of version 1 were played with the default rules in the bordered arena
+        let mut rules = Rules::default();
+        let mut level = None;
+
+        while let Some((name, value)) = lines
+            .peek()
+            .filter(|_| version >= 2)
+            .and_then(|line| rule(line))
+        {
+            match name {
+                "movement" => rules.movement = parse_movement(value)?,
+                "reverse-protection" => rules.reverse_protection = parse_on_off(value)?,
+                "progression" => rules.progression = parse_progression(value)?,
+                "arena" => rules.arena = parse_arena(value)?,
+                "wrap-around" => rules.wrap_around = parse_on_off(value)?,
+                "mode" => rules.mode = parse_mode(value)?,
+                "level" => level = Some(value.trim().to_string()),
+                _ => return Err(anyhow!("Unknown rule '{}' in replay", name)),
+            }
+
+            lines.next();
+        }
+
+        let mut replay = Replay::new(seed, rules, level);
+
+        for line in lines {
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<i64>())
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid step '{}' in replay", line))?;
+
+            let Some((&tick, directions)) = values.split_first() else {
+                return Err(anyhow!("Invalid step '{}' in replay", line));
+            };
+
+            if directions.is_empty()
+                || directions.len() % 2 != 0
+                || directions.len() > 2 * MAX_PLAYERS
+            {
+                return Err(anyhow!("Invalid step '{}' in replay", line));
+            }
+
+            if tick < 0
+                || replay
+                    .steps
+                    .last()
+                    .is_some_and(|step| step.tick >= tick as u64)
+            {
+                return Err(anyhow!("Steps of replay are not in order"));
+            }
+
+            let mut input = Input::default();
+
+            for (player, direction) in directions.chunks(2).enumerate() {
+                input.directions[player] = Direction::new(direction[0] as i32, direction[1] as i32);
+            }
+
+            replay.steps.push(Step {
+                tick: tick as u64,
+                input,
+            });
+        }
+
+        Ok(replay)
+    }
+}
+
+/// Splits a line into the name and value of a rule, if it starts with a letter.
+fn rule(line: &str) -> Option<(&str, &str)> {
+    line.split_once(' ')
+        .filter(|(name, _)| name.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+fn movement_name(movement: Movement) -> &'static str {
+    match movement {
+        Movement::EightWay => "eight-way",
+        Movement::FourWay => "four-way",
+        Movement::Turns => "turns",
+    }
+}
+
+fn parse_movement(value: &str) -> Result<Movement> {
+    [Movement::EightWay, Movement::FourWay, Movement::Turns]
+        .into_iter()
+        .find(|&movement| movement_name(movement) == value.trim())
+        .ok_or_else(|| anyhow!("Invalid movement '{}' in replay", value))
+}
+
+fn progression_name(progression: Progression) -> &'static str {
+    match progression {
+        Progression::Relaxed => "relaxed",
+        Progression::Classic => "classic",
+        Progression::Frantic => "frantic",
+    }
+}
+
+fn parse_progression(value: &str) -> Result<Progression> {
+    Progression::ALL
+        .into_iter()
+        .find(|&progression| progression_name(progression) == value.trim())
+        .ok_or_else(|| anyhow!("Invalid progression '{}' in replay", value))
+}
+
+fn arena_name(arena: ArenaSize) -> &'static str {
+    match arena {
+        ArenaSize::Small => "small",
+        ArenaSize::Medium => "medium",
+        ArenaSize::Large => "large",
+        ArenaSize::Ultrawide => "ultrawide",
+    }
+}
+
+fn parse_arena(value: &str) -> Result<ArenaSize> {
+    ArenaSize::ALL
+        .into_iter()
+        .find(|&arena| arena_name(arena) == value.trim())
+        .ok_or_else(|| anyhow!("Invalid arena '{}' in replay", value))
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Single => "single",
+        Mode::Versus => "versus",
+        Mode::Coop => "coop",
+        Mode::Computer => "computer",
+    }
+}
+
+fn parse_mode(value: &str) -> Result<Mode> {
+    Mode::ALL
+        .into_iter()
+        .find(|&mode| mode_name(mode) == value.trim())
+        .ok_or_else(|| anyhow!("Invalid mode '{}' in replay", value))
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "on" } else { "off" }
+}
+
+fn parse_on_off(value: &str) -> Result<bool> {
+    match value.trim() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(anyhow!(
+            "Expected 'on' or 'off' instead of '{}' in replay",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_survives_a_round_trip() {
+        let rules = Rules {
+            movement: Movement::FourWay,
+            reverse_protection: true,
+            mode: Mode::Versus,
+            ..Rules::default()
+        };
+        let mut replay = Replay::new(7, rules, Some("levels/pillars.level".to_string()));
+        let mut input = Input::default();
+        input.directions[1] = Direction::new(0, 1);
+        replay.record(3, input);
+
+        let parsed: Replay = replay.to_string().parse().unwrap();
+
+        assert_eq!(parsed, replay);
+    }
+
+    #[test]
+    fn version_1_is_played_with_the_default_rules() {
+        let replay: Replay = "diplopod replay 1\nseed 42\n2 1 0\n5 0 -1\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(replay.seed(), 42);
+        assert_eq!(replay.rules(), Rules::default());
+        assert_eq!(replay.level(), None);
+        assert_eq!(replay.input(4).directions[0], Direction::new(1, 0));
+        assert_eq!(replay.input(5).directions[0], Direction::new(0, -1));
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let error = "diplopod replay 3\nseed 42\n"
+            .parse::<Replay>()
+            .unwrap_err();
+
+        assert!(error.to_string().contains("Unsupported replay version 3"));
+        assert!("diplopod replay\nseed 42\n".parse::<Replay>().is_err());
+        assert!("something else\nseed 42\n".parse::<Replay>().is_err());
+    }
+}